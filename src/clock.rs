@@ -0,0 +1,51 @@
+use legion::{prelude::*, systems::SystemId};
+use std::time::{Duration, Instant};
+
+/// A resource that tells tasks how much time has passed. It is advanced once per frame, either by
+/// the system created with `build_task_clock_system`, or by calling `advance` from your own game
+/// loop if you already keep track of time (or want to pause it).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TaskClock {
+    tick: u64,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl TaskClock {
+    /// Moves the clock forward by one tick, which took `delta`.
+    pub fn advance(&mut self, delta: Duration) {
+        self.tick += 1;
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+
+    /// Number of times the clock has been advanced.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Time taken by the last tick.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Total time of all ticks.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Creates a system that advances the `TaskClock` resource by the wall-clock time since it last
+/// ran. Schedule it before any task runners that read the clock.
+pub fn build_task_clock_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+    let mut last_time: Option<Instant> = None;
+
+    SystemBuilder::new(id)
+        .write_resource::<TaskClock>()
+        .build(move |_, _, clock, _| {
+            let now = Instant::now();
+            let delta = last_time.map_or(Duration::default(), |last| now - last);
+            last_time = Some(now);
+            clock.advance(delta);
+        })
+}
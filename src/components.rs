@@ -1,33 +1,326 @@
+use crate::context::{ParTaskContext, TaskContext};
+use crate::graph_builder::{Cons, OwnedGraph, SharedGraph, TaskGraph};
+use crate::handle::{HandleSlot, TaskHandle};
+
 use legion::prelude::*;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::any::Any;
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+    Arc, Mutex, OnceLock,
+};
+use std::time::{Duration, Instant};
 
 /// An ephemeral component that needs access to `Data` to run some task. Will be run by `run_tasks`
 /// in a system with access to `task_runner_query` and `Data`.
 pub trait TaskComponent<'a>: Send + Sync {
     type Data;
 
-    /// Returns `true` iff the task is complete.
-    fn run(&mut self, data: &mut Self::Data) -> bool;
+    /// Advances the task, returning whether it is still running, complete, or failed.
+    fn run(&mut self, data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus;
+
+    /// Called once, right before the first `run`, after `set_input`. Use this for setup that needs
+    /// `Data`.
+    fn on_unblocked(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) {}
+
+    /// Called once `run` returns `TaskStatus::Complete` or `TaskStatus::Failed`, after
+    /// `take_output`. Only called after `on_unblocked`, so not for a task that rejected its input
+    /// in `set_input`. Not called for a cancelled task, which gets `on_cancel` instead.
+    fn on_finished(
+        &mut self,
+        _data: &mut Self::Data,
+        _ctx: &mut TaskContext,
+        _outcome: &TaskOutcome,
+    ) {
+    }
+
+    /// Called if the task is cancelled after it was unblocked, but before it finished. Use this to
+    /// release anything the task holds.
+    fn on_cancel(&mut self) {}
+
+    /// Called right before the first `run` with the output of the entity this task depends on, if
+    /// that entity produced one. Returning an error, e.g. from `TaskOutput::downcast` when the
+    /// input has the wrong type, fails the task without running it or calling its other hooks.
+    fn set_input(&mut self, _input: TaskOutput) -> Result<(), TaskError> {
+        Ok(())
+    }
+
+    /// Called once `run` returns `TaskStatus::Complete`. The returned output is passed on to the
+    /// tasks that depend on this one.
+    fn take_output(&mut self) -> Option<TaskOutput> {
+        None
+    }
+}
+
+/// A task that only needs shared access to its `Data` while it runs, so that `par_run_tasks` can
+/// run the tasks of its type in parallel. The hooks of `TaskComponent` still get exclusive access;
+/// they are called before and after the parallel part. `run` can simply call `par_run` with
+/// `TaskContext::without_commands`, so that the task can also be run by `run_tasks`.
+pub trait ParTaskComponent<'a>: TaskComponent<'a> {
+    /// Advances the task, like `TaskComponent::run`.
+    fn par_run(&mut self, data: &Self::Data, ctx: &mut ParTaskContext) -> TaskStatus;
+}
+
+/// Type-erased call to `TaskComponent::on_cancel`, since the task manager doesn't know the type of
+/// each task.
+pub(crate) type CancelHook = fn(&mut World, Entity);
+
+fn call_on_cancel<'a, T: 'static + TaskComponent<'a>>(world: &mut World, entity: Entity) {
+    if let Some(mut task) = world.get_component_mut::<T>(entity) {
+        task.on_cancel();
+    }
+}
+
+/// The result of a single call to `TaskComponent::run`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TaskStatus {
+    /// The task needs to be run again.
+    Running,
+    /// The task finished successfully.
+    Complete,
+    /// The task gave up. It will not be run again.
+    Failed(TaskError),
+}
+
+/// Describes why a task failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaskError {
+    message: String,
+}
+
+impl TaskError {
+    pub fn new(message: impl Into<String>) -> Self {
+        TaskError {
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+/// A value produced by a completed task, which is passed on to the tasks that depend on it.
+/// Control nodes pass on the output of their sub-graph; retries, timeouts, branches, and loops pass
+/// on the output of the last entity they ran, and races pass on the output of the winning prong.
+/// Forks and other quorums have no output.
+#[derive(Clone)]
+pub struct TaskOutput {
+    value: Arc<dyn Any + Send + Sync>,
+    type_name: &'static str,
+}
+
+impl TaskOutput {
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        TaskOutput {
+            value: Arc::new(value),
+            type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// Returns the value if it's a `T`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Returns the value if it's a `T`, or an error naming both types if it isn't.
+    pub fn downcast<T: Any>(&self) -> Result<&T, TaskError> {
+        self.get().ok_or_else(|| {
+            TaskError::new(format!(
+                "expected an output of type {}, got {}",
+                std::any::type_name::<T>(),
+                self.type_name
+            ))
+        })
+    }
+
+    /// The name of the type of the value, as given by `std::any::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl fmt::Debug for TaskOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TaskOutput({})", self.type_name)
+    }
+}
+
+/// How a task or fork entity finished.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TaskOutcome {
+    Complete,
+    Failed(TaskError),
+    /// The entity was never allowed to finish, e.g. because another part of its graph failed.
+    Cancelled,
 }
 
+const RUNNING: u8 = 0;
+const COMPLETE: u8 = 1;
+const FAILED: u8 = 2;
+const CANCELLED: u8 = 3;
+
 #[doc(hidden)]
 #[derive(Default)]
 pub struct TaskProgress {
-    pub(crate) is_complete: AtomicBool,
-    pub(crate) is_unblocked: bool,
+    // Runners only get read access to `TaskProgress`, and the task manager maintains graphs in
+    // parallel, so everything that changes is written through atomics and locks.
+    pub(crate) status: AtomicU8,
+    pub(crate) error: Mutex<Option<TaskError>>,
+    pub(crate) unblocked: OnceLock<Unblocked>,
+    // Forks don't have a hook.
+    pub(crate) on_cancel: Option<CancelHook>,
+    // Set by the task manager on unblocking, and taken by the runner.
+    pub(crate) input: Mutex<Option<TaskOutput>>,
+    pub(crate) output: Mutex<Option<TaskOutput>>,
+    // Whether the task manager has sent a `TaskEvent::Finished` for this entity.
+    pub(crate) is_reported: AtomicBool,
+    // The last entity of a graph spawned by the task, which must finish before the task runs again.
+    pub(crate) awaited: Mutex<Option<Entity>>,
+    // The tick of the `TaskClock` on which the runner first ran the task, set once it has called
+    // `TaskComponent::on_unblocked`.
+    pub(crate) started: OnceLock<u64>,
+    // Bits of the `f32` fraction last reported by the task.
+    pub(crate) fraction: AtomicU32,
+}
+
+/// When the task manager unblocked an entity.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Unblocked {
+    // Number of times the task manager system had run.
+    pub(crate) tick: u64,
+    pub(crate) time: Instant,
+    // The final entity of the graph at the time.
+    pub(crate) root: Entity,
 }
 
 impl TaskProgress {
-    pub(crate) fn is_complete(&self) -> bool {
-        self.is_complete.load(Ordering::Relaxed)
+    pub(crate) fn is_unblocked(&self) -> bool {
+        self.unblocked.get().is_some()
+    }
+
+    /// Returns `true` iff the entity is a task, rather than a fork or a control entity.
+    pub(crate) fn is_task(&self) -> bool {
+        self.on_cancel.is_some()
+    }
+
+    /// Returns `true` iff the task is either complete or failed.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.status.load(Ordering::Relaxed) != RUNNING
+    }
+
+    pub(crate) fn outcome(&self) -> Option<TaskOutcome> {
+        match self.status.load(Ordering::Relaxed) {
+            COMPLETE => Some(TaskOutcome::Complete),
+            FAILED => Some(TaskOutcome::Failed(
+                self.error
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .expect("Failed task must have an error"),
+            )),
+            CANCELLED => Some(TaskOutcome::Cancelled),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn finish(&self, outcome: TaskOutcome) {
+        match outcome {
+            TaskOutcome::Complete => self.complete(),
+            TaskOutcome::Failed(error) => self.fail(error),
+            TaskOutcome::Cancelled => self.cancel(),
+        }
     }
 
     pub(crate) fn complete(&self) {
-        self.is_complete.store(true, Ordering::Relaxed);
+        self.status.store(COMPLETE, Ordering::Relaxed);
+    }
+
+    pub(crate) fn fail(&self, error: TaskError) {
+        *self.error.lock().unwrap() = Some(error);
+        self.status.store(FAILED, Ordering::Relaxed);
     }
 
-    pub(crate) fn unblock(&mut self) {
-        self.is_unblocked = true;
+    pub(crate) fn cancel(&self) {
+        self.status.store(CANCELLED, Ordering::Relaxed);
+    }
+
+    /// The `on_cancel` hook that must be called if this task is cancelled now.
+    pub(crate) fn cancel_hook(&self) -> Option<CancelHook> {
+        if self.is_unblocked() {
+            self.on_cancel
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn unblock(&self, unblocked: Unblocked, input: Option<TaskOutput>) {
+        *self.input.lock().unwrap() = input;
+        let _ = self.unblocked.set(unblocked);
+    }
+
+    pub(crate) fn await_entity(&self, entity: Entity) {
+        *self.awaited.lock().unwrap() = Some(entity);
+    }
+
+    pub(crate) fn awaited(&self) -> Option<Entity> {
+        *self.awaited.lock().unwrap()
+    }
+
+    /// Lets a task that was awaiting a graph run again.
+    pub(crate) fn resume(&self, input: Option<TaskOutput>) {
+        *self.input.lock().unwrap() = input;
+        *self.awaited.lock().unwrap() = None;
+    }
+
+    /// Records `tick` as the one the task first ran on. Returns `true` the first time it is called.
+    pub(crate) fn start(&self, tick: u64) -> bool {
+        self.started.set(tick).is_ok()
+    }
+
+    pub(crate) fn is_reported(&self) -> bool {
+        self.is_reported.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` the first time it is called.
+    pub(crate) fn report(&self) -> bool {
+        !self.is_reported.swap(true, Ordering::Relaxed)
+    }
+
+    /// How much of its work the entity has done, from 0.0 to 1.0. Finished entities are done.
+    pub(crate) fn fraction(&self) -> f32 {
+        if self.is_finished() {
+            1.0
+        } else {
+            f32::from_bits(self.fraction.load(Ordering::Relaxed))
+        }
+    }
+
+    pub(crate) fn set_fraction(&self, fraction: f32) {
+        // `clamp` would let NaN through, which would spread to the progress of the whole graph.
+        let fraction = if fraction.is_finite() { fraction } else { 0.0 };
+        self.fraction
+            .store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn take_input(&self) -> Option<TaskOutput> {
+        self.input.lock().unwrap().take()
+    }
+
+    pub(crate) fn output(&self) -> Option<TaskOutput> {
+        self.output.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_output(&self, output: Option<TaskOutput>) {
+        *self.output.lock().unwrap() = output;
     }
 }
 
@@ -41,31 +334,278 @@ pub struct SingleEdge {
 #[derive(Clone, Default)]
 pub struct MultiEdge {
     pub(crate) children: Vec<Entity>,
+    pub(crate) join: Join,
+}
+
+/// How many prongs of a fork must complete before the fork does.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Join {
+    /// Every prong.
+    #[default]
+    All,
+    /// Any `needed` prongs. What happens to the others is decided by `rest`.
+    Quorum { needed: usize, rest: OnQuorum },
+}
+
+/// What a quorum does with its unfinished prongs once enough of them have completed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OnQuorum {
+    /// Cancel them.
+    CancelRest,
+    /// Keep running them, but don't wait for them. They are maintained until they finish, even
+    /// after the rest of the graph has finished, and cancelled if the graph is deleted.
+    LeaveRunning,
+}
+
+impl Join {
+    pub(crate) const RACE: Join = Join::Quorum {
+        needed: 1,
+        rest: OnQuorum::CancelRest,
+    };
 }
 
 impl MultiEdge {
     fn add_child(&mut self, entity: Entity) {
         self.children.push(entity);
     }
+
+    /// Returns the prongs that may still be running after the fork has completed.
+    pub(crate) fn prongs_left_running(&self) -> &[Entity] {
+        match self.join {
+            Join::Quorum {
+                rest: OnQuorum::LeaveRunning,
+                ..
+            } => &self.children,
+            _ => &[],
+        }
+    }
+}
+
+/// Edge from an entity that owns a sub-graph, like a retry, to the last entity of that sub-graph.
+/// The sub-graph only starts once its owner is unblocked.
+#[doc(hidden)]
+#[derive(Clone, Default)]
+pub struct InnerEdge {
+    pub(crate) child: Option<Entity>,
+}
+
+#[doc(hidden)]
+pub struct RetryNode {
+    pub(crate) graph: SharedGraph,
+    pub(crate) attempts_left: u32,
+}
+
+/// A limit on how long a `TaskGraph` node may take after it is unblocked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Timeout {
+    /// Number of runs of the task manager system.
+    Ticks(u64),
+    /// Wall-clock time.
+    Duration(Duration),
+}
+
+/// What happens to a node that is still running when its `Timeout` runs out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OnTimeout {
+    /// Cancel the node and fail it, so the failure goes through the graph's `OnFailure`.
+    Fail,
+    /// Cancel the node, along with the tasks that depend on it.
+    Cancel,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct TimeoutNode {
+    pub(crate) timeout: Timeout,
+    pub(crate) on_timeout: OnTimeout,
+}
+
+impl TimeoutNode {
+    pub(crate) fn has_expired(&self, unblocked: Unblocked, tick: u64, time: Instant) -> bool {
+        match self.timeout {
+            Timeout::Ticks(ticks) => tick.saturating_sub(unblocked.tick) >= ticks,
+            Timeout::Duration(duration) => time.duration_since(unblocked.time) >= duration,
+        }
+    }
+}
+
+/// Decides which side of a branch runs, or whether a loop runs again, given the `World` as it is
+/// once the branch or loop is unblocked. Predicates made with `with_resources` can also read the
+/// `Resources`, which are only available to `evaluate_task_predicates`.
+#[derive(Clone)]
+pub struct Predicate(PredicateFn);
+
+#[derive(Clone)]
+enum PredicateFn {
+    World(Arc<WorldFn>),
+    WorldAndResources(Arc<WorldAndResourcesFn>),
+}
+
+type WorldFn = dyn Fn(&World) -> bool + Send + Sync;
+type WorldAndResourcesFn = dyn Fn(&World, &Resources) -> bool + Send + Sync;
+
+impl Predicate {
+    pub fn new<F: 'static + Fn(&World) -> bool + Send + Sync>(predicate: F) -> Self {
+        Predicate(PredicateFn::World(Arc::new(predicate)))
+    }
+
+    /// Makes a predicate that also reads resources. It is only evaluated if
+    /// `evaluate_task_predicates` is scheduled.
+    pub fn with_resources<F>(predicate: F) -> Self
+    where
+        F: 'static + Fn(&World, &Resources) -> bool + Send + Sync,
+    {
+        Predicate(PredicateFn::WorldAndResources(Arc::new(predicate)))
+    }
+
+    pub(crate) fn needs_resources(&self) -> bool {
+        matches!(self.0, PredicateFn::WorldAndResources(_))
+    }
+
+    pub(crate) fn evaluate(&self, world: &World, resources: Option<&Resources>) -> bool {
+        match (&self.0, resources) {
+            (PredicateFn::World(predicate), _) => predicate(world),
+            (PredicateFn::WorldAndResources(predicate), Some(resources)) => {
+                predicate(world, resources)
+            }
+            (PredicateFn::WorldAndResources(_), None) => {
+                panic!("Tried to evaluate a predicate that needs resources without them")
+            }
+        }
+    }
+
+    fn as_ptr(&self) -> *const () {
+        match &self.0 {
+            PredicateFn::World(predicate) => Arc::as_ptr(predicate) as *const (),
+            PredicateFn::WorldAndResources(predicate) => Arc::as_ptr(predicate) as *const (),
+        }
+    }
+}
+
+impl fmt::Debug for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Predicate({:p})", self.as_ptr())
+    }
+}
+
+/// Predicates are only equal if they are the same closure.
+impl PartialEq for Predicate {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ptr() == other.as_ptr()
+    }
+}
+
+impl Eq for Predicate {}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct BranchNode {
+    pub(crate) predicate: Predicate,
+    pub(crate) then_graph: SharedGraph,
+    pub(crate) else_graph: SharedGraph,
+    // Set once the predicate has been submitted for evaluation.
+    pub(crate) is_deciding: bool,
+}
+
+#[doc(hidden)]
+pub struct RepeatNode {
+    pub(crate) graph: SharedGraph,
+    pub(crate) iterations_left: u32,
+}
+
+#[doc(hidden)]
+pub struct WhileNode {
+    pub(crate) predicate: Predicate,
+    pub(crate) graph: SharedGraph,
+    // Set while the predicate has been submitted for evaluation.
+    pub(crate) is_deciding: bool,
+}
+
+/// Added to a branch or while entity whose predicate needs resources, until
+/// `evaluate_task_predicates` evaluates it and calls `decide` with the result.
+#[derive(Clone)]
+pub(crate) struct PendingDecision {
+    pub(crate) predicate: Predicate,
+    pub(crate) decide: fn(&mut World, Entity, bool),
 }
 
 #[doc(hidden)]
 #[derive(Clone, Copy, Default)]
 pub struct FinalTag {
     pub(crate) on_completion: OnCompletion,
+    pub(crate) on_failure: OnFailure,
+}
+
+/// Added by the task manager to tasks once they are unblocked, and removed when they finish, so
+/// that `task_runner_query` only matches the tasks that can run.
+#[doc(hidden)]
+#[derive(Clone, Copy, Default)]
+pub struct RunnableTag;
+
+/// A closure called by the task manager with the final entity of a graph that just finished.
+pub type GraphCallback = Box<dyn FnOnce(&mut CommandBuffer, Entity) + Send>;
+
+/// Closures to call when a graph finishes, in the same run of the task manager, before the graph is
+/// cleaned up according to its `OnCompletion`. A cancelled graph calls neither.
+#[derive(Default)]
+pub struct GraphCallbacks {
+    on_complete: Option<GraphCallback>,
+    on_fail: Option<GraphCallback>,
 }
 
+impl GraphCallbacks {
+    /// Calls `callback` if the graph completes.
+    pub fn on_complete<F>(mut self, callback: F) -> Self
+    where
+        F: 'static + FnOnce(&mut CommandBuffer, Entity) + Send,
+    {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` if the graph fails.
+    pub fn on_fail<F>(mut self, callback: F) -> Self
+    where
+        F: 'static + FnOnce(&mut CommandBuffer, Entity) + Send,
+    {
+        self.on_fail = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn take(&mut self, outcome: &TaskOutcome) -> Option<GraphCallback> {
+        match outcome {
+            TaskOutcome::Complete => self.on_complete.take(),
+            TaskOutcome::Failed(_) => self.on_fail.take(),
+            TaskOutcome::Cancelled => None,
+        }
+    }
+}
+
+// Callbacks only need to be `Send`, but components must be `Sync`.
+#[doc(hidden)]
+pub struct CallbacksSlot(pub(crate) Mutex<GraphCallbacks>);
+
 /// What to do to a final task and its descendents when they complete.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum OnCompletion {
+    #[default]
     None,
     Delete,
 }
 
-impl Default for OnCompletion {
-    fn default() -> Self {
-        OnCompletion::None
-    }
+/// What to do to the rest of a task graph when one of its tasks fails.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OnFailure {
+    /// Fail the whole graph right away. Every unfinished task in the graph is cancelled, including
+    /// the other prongs of any fork that contains the failed task.
+    #[default]
+    Abort,
+    /// Fail only the tasks that depend on the failed task and cancel the tasks that can no longer
+    /// start. Other prongs of a fork that contains the failed task keep running, and the fork fails
+    /// once all of them have finished.
+    SkipDependents,
+    /// Treat a failed task as if it had completed, unblocking its dependents.
+    Continue,
 }
 
 /// Gives read-only access to the task meta-components in order to query the state of task entities.
@@ -83,7 +623,10 @@ pub fn make_task<'a, T: 'static + TaskComponent<'a>>(
 ) -> Entity {
     let entity = cmd
         .start_entity()
-        .with_component(TaskProgress::default())
+        .with_component(TaskProgress {
+            on_cancel: Some(call_on_cancel::<T>),
+            ..Default::default()
+        })
         .with_component(task_component)
         .build();
     log::debug!("Created task {:?}", entity);
@@ -91,21 +634,249 @@ pub fn make_task<'a, T: 'static + TaskComponent<'a>>(
     entity
 }
 
-/// Mark `entity` as "final," i.e. a task with no parent.
-pub fn finalize(cmd: &CommandBuffer, entity: Entity, on_completion: OnCompletion) {
-    cmd.add_component(entity, FinalTag { on_completion });
+/// Mark `entity` as "final," i.e. a task with no parent. Returns a handle for checking on the
+/// graph.
+pub fn finalize(
+    cmd: &CommandBuffer,
+    entity: Entity,
+    on_completion: OnCompletion,
+    on_failure: OnFailure,
+) -> TaskHandle {
+    cmd.add_component(
+        entity,
+        FinalTag {
+            on_completion,
+            on_failure,
+        },
+    );
+    let slot = HandleSlot::new(entity);
+    cmd.add_component(entity, slot.clone());
     log::debug!("Finalized task {:?}", entity);
+
+    TaskHandle::new(slot)
 }
 
-/// Create a new fork entity with no children.
+/// Attach `callbacks` to the final `entity` of a graph.
+pub fn set_callbacks(cmd: &CommandBuffer, entity: Entity, callbacks: GraphCallbacks) {
+    cmd.add_component(entity, CallbacksSlot(Mutex::new(callbacks)));
+    log::debug!("Set callbacks of {:?}", entity);
+}
+
+/// Assemble `graph` so that it starts once `existing_final` is complete, and make its last entity
+/// the final entity of the combined graph. The `FinalTag`, handle and callbacks of `existing_final`
+/// move to the new final entity, so handles returned for the original graph report on the combined
+/// graph. Returns the new final entity, which can be appended to in turn. If the graph of
+/// `existing_final` has already finished, or was deleted, by the time the commands are flushed,
+/// `graph` starts right away as a graph of its own, with the default `FinalTag`.
+pub fn append(cmd: &mut CommandBuffer, existing_final: Entity, graph: TaskGraph) -> Entity {
+    let graph = OwnedGraph(graph.remove_nil());
+    if let Cons::Nil = graph.0 {
+        return existing_final;
+    }
+    let (first_entity, last_entity) = graph.0._assemble(None, cmd);
+    join(cmd, first_entity, existing_final);
+    cmd.exec_mut(move |world| {
+        let final_tag = world
+            .get_component::<FinalTag>(existing_final)
+            .map(|tag| *tag);
+        let final_tag = match final_tag {
+            Some(final_tag) => final_tag,
+            None => {
+                log::debug!(
+                    "{:?} is no longer final, so appended task {:?} is final instead",
+                    existing_final,
+                    last_entity
+                );
+                // There is nothing left to wait for.
+                let _ = world.remove_component::<SingleEdge>(first_entity);
+                world
+                    .add_component(last_entity, FinalTag::default())
+                    .unwrap();
+                return;
+            }
+        };
+        let slot = world
+            .get_component::<HandleSlot>(existing_final)
+            .map(|slot| (*slot).clone());
+        let callbacks = world
+            .get_component::<CallbacksSlot>(existing_final)
+            .map(|slot| std::mem::take(&mut *slot.0.lock().unwrap()));
+
+        world.remove_component::<FinalTag>(existing_final).unwrap();
+        world.add_component(last_entity, final_tag).unwrap();
+        if let Some(slot) = slot {
+            world
+                .remove_component::<HandleSlot>(existing_final)
+                .unwrap();
+            slot.move_to(last_entity);
+            world.add_component(last_entity, slot).unwrap();
+        }
+        if let Some(callbacks) = callbacks {
+            world
+                .remove_component::<CallbacksSlot>(existing_final)
+                .unwrap();
+            world
+                .add_component(last_entity, CallbacksSlot(Mutex::new(callbacks)))
+                .unwrap();
+        }
+    });
+    log::debug!(
+        "Submitted command to append {:?} to final task {:?}",
+        last_entity,
+        existing_final
+    );
+
+    last_entity
+}
+
+/// Create a new fork entity with no children. Forks also have a `TaskProgress`, but it is only
+/// written by the task manager.
 pub fn make_fork(cmd: &mut CommandBuffer) -> Entity {
+    let entity = make_multi_edge_entity(cmd, Join::All);
+    log::debug!("Created fork {:?}", entity);
+
+    entity
+}
+
+/// Create a new race entity with no children. A race is a fork that completes as soon as one of its
+/// prongs completes, cancelling the other prongs. It only fails once none of its prongs can
+/// complete.
+pub fn make_race(cmd: &mut CommandBuffer) -> Entity {
+    let entity = make_multi_edge_entity(cmd, Join::RACE);
+    log::debug!("Created race {:?}", entity);
+
+    entity
+}
+
+/// Create a new quorum entity with no children. A quorum is a fork that completes as soon as
+/// `needed` of its prongs complete, and then handles its other prongs according to `on_quorum`. It
+/// only fails once too few of its prongs can complete.
+pub fn make_quorum(cmd: &mut CommandBuffer, needed: usize, on_quorum: OnQuorum) -> Entity {
+    let entity = make_multi_edge_entity(
+        cmd,
+        Join::Quorum {
+            needed,
+            rest: on_quorum,
+        },
+    );
+    log::debug!("Created quorum {:?} needing {} prongs", entity, needed);
+
+    entity
+}
+
+fn make_multi_edge_entity(cmd: &mut CommandBuffer, join: Join) -> Entity {
+    cmd.start_entity()
+        .with_component(MultiEdge {
+            children: Vec::new(),
+            join,
+        })
+        .with_component(TaskProgress::default())
+        .build()
+}
+
+/// Create a new retry entity. Once unblocked, it assembles a copy of `graph` and waits for it to
+/// finish. Each time the copy fails, it is deleted and replaced with a fresh one, until `graph` has
+/// been tried `attempts` times. Retry entities also have a `TaskProgress` that is only written by
+/// the task manager.
+pub fn make_retry(cmd: &mut CommandBuffer, graph: Arc<TaskGraph>, attempts: u32) -> Entity {
+    assert!(attempts > 0, "Tried to make a retry with no attempts");
     let entity = cmd
         .start_entity()
-        .with_component(MultiEdge::default())
-        // BUG: builder seems to require at least 2 components
-        .with_component(())
+        .with_component(RetryNode {
+            graph: SharedGraph(graph),
+            attempts_left: attempts,
+        })
+        .with_component(InnerEdge::default())
+        .with_component(TaskProgress::default())
         .build();
-    log::debug!("Created fork {:?}", entity);
+    log::debug!("Created retry {:?}", entity);
+
+    entity
+}
+
+/// Create a new branch entity. Once unblocked, it evaluates `predicate` and assembles a copy of
+/// either `then_graph` or `else_graph`, then finishes with the outcome of that copy. The other
+/// graph is never assembled. Branch entities also have a `TaskProgress` that is only written by the
+/// task manager.
+pub fn make_branch(
+    cmd: &mut CommandBuffer,
+    predicate: Predicate,
+    then_graph: Arc<TaskGraph>,
+    else_graph: Arc<TaskGraph>,
+) -> Entity {
+    let entity = cmd
+        .start_entity()
+        .with_component(BranchNode {
+            predicate,
+            then_graph: SharedGraph(then_graph),
+            else_graph: SharedGraph(else_graph),
+            is_deciding: false,
+        })
+        .with_component(InnerEdge::default())
+        .with_component(TaskProgress::default())
+        .build();
+    log::debug!("Created branch {:?}", entity);
+
+    entity
+}
+
+/// Create a new repeat entity. Once unblocked, it assembles a copy of `graph` and waits for it to
+/// complete, then deletes it and assembles another, until `graph` has run `iterations` times. Stops
+/// early with the outcome of an iteration that fails or is cancelled. Repeat entities also have a
+/// `TaskProgress` that is only written by the task manager.
+pub fn make_repeat(cmd: &mut CommandBuffer, graph: Arc<TaskGraph>, iterations: u32) -> Entity {
+    let entity = cmd
+        .start_entity()
+        .with_component(RepeatNode {
+            graph: SharedGraph(graph),
+            iterations_left: iterations,
+        })
+        .with_component(InnerEdge::default())
+        .with_component(TaskProgress::default())
+        .build();
+    log::debug!("Created repeat {:?}", entity);
+
+    entity
+}
+
+/// Create a new while entity. Like a repeat entity, except that `predicate` is evaluated before
+/// each iteration, and the entity completes once it returns `false`.
+pub fn make_while(cmd: &mut CommandBuffer, predicate: Predicate, graph: Arc<TaskGraph>) -> Entity {
+    let entity = cmd
+        .start_entity()
+        .with_component(WhileNode {
+            predicate,
+            graph: SharedGraph(graph),
+            is_deciding: false,
+        })
+        .with_component(InnerEdge::default())
+        .with_component(TaskProgress::default())
+        .build();
+    log::debug!("Created while {:?}", entity);
+
+    entity
+}
+
+/// Create a new timeout entity that finishes with the outcome of `inner`, unless `timeout` runs out
+/// first, in which case `inner` and its descendents are cancelled. `inner` must be the last entity
+/// of a graph that doesn't depend on anything else; it will only start once the timeout entity is
+/// unblocked. Timeout entities also have a `TaskProgress` that is only written by the task manager.
+pub fn make_timeout(
+    cmd: &mut CommandBuffer,
+    inner: Entity,
+    timeout: Timeout,
+    on_timeout: OnTimeout,
+) -> Entity {
+    let entity = cmd
+        .start_entity()
+        .with_component(TimeoutNode {
+            timeout,
+            on_timeout,
+        })
+        .with_component(InnerEdge { child: Some(inner) })
+        .with_component(TaskProgress::default())
+        .build();
+    log::debug!("Created timeout {:?} around {:?}", entity, inner);
 
     entity
 }
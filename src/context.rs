@@ -0,0 +1,163 @@
+use crate::clock::TaskClock;
+use crate::components::{SingleEdge, TaskProgress, Unblocked};
+use crate::graph_builder::{Cons, OwnedGraph, TaskGraph};
+
+use legion::prelude::*;
+use std::time::Duration;
+
+/// Passed to `TaskComponent::run`. Tells a task about itself and its graph, and lets it act on the
+/// world beyond its own `Data`.
+pub struct TaskContext<'a> {
+    base: ParTaskContext<'a>,
+    cmd: &'a mut CommandBuffer,
+}
+
+/// Passed to `ParTaskComponent::par_run`. Tells a task about itself and its graph like a
+/// `TaskContext`, but has no command buffer, since other tasks are running at the same time.
+pub struct ParTaskContext<'a> {
+    entity: Entity,
+    progress: &'a TaskProgress,
+    clock: &'a TaskClock,
+}
+
+impl<'a> ParTaskContext<'a> {
+    pub(crate) fn new(entity: Entity, progress: &'a TaskProgress, clock: &'a TaskClock) -> Self {
+        ParTaskContext {
+            entity,
+            progress,
+            clock,
+        }
+    }
+
+    /// The entity of the running task.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The final entity of the task's graph, as of when the task was unblocked.
+    pub fn root(&self) -> Entity {
+        self.unblocked().root
+    }
+
+    /// The tick of the `TaskClock`.
+    pub fn tick(&self) -> u64 {
+        self.clock.tick()
+    }
+
+    /// The tick on which the task's runner first ran it, right after calling
+    /// `TaskComponent::on_unblocked`.
+    pub fn unblocked_tick(&self) -> u64 {
+        *self
+            .progress
+            .started
+            .get()
+            .expect("Tasks are started before they get a context")
+    }
+
+    /// Time taken by the last tick of the `TaskClock`.
+    pub fn delta_time(&self) -> Duration {
+        self.clock.delta()
+    }
+
+    fn unblocked(&self) -> Unblocked {
+        *self
+            .progress
+            .unblocked
+            .get()
+            .expect("Only unblocked tasks are run")
+    }
+
+    /// Reports how much of its work the task has done, from 0.0 to 1.0. Values outside of that
+    /// range are clamped, and NaN or infinite values count as 0.0. Once the task finishes, its
+    /// progress is 1.0 regardless.
+    pub fn report_progress(&mut self, fraction: f32) {
+        self.progress.set_fraction(fraction);
+    }
+}
+
+impl<'a> TaskContext<'a> {
+    pub(crate) fn new(
+        entity: Entity,
+        progress: &'a TaskProgress,
+        clock: &'a TaskClock,
+        cmd: &'a mut CommandBuffer,
+    ) -> Self {
+        TaskContext {
+            base: ParTaskContext::new(entity, progress, clock),
+            cmd,
+        }
+    }
+
+    /// The entity of the running task.
+    pub fn entity(&self) -> Entity {
+        self.base.entity()
+    }
+
+    /// The final entity of the task's graph, as of when the task was unblocked.
+    pub fn root(&self) -> Entity {
+        self.base.root()
+    }
+
+    /// The tick of the `TaskClock`.
+    pub fn tick(&self) -> u64 {
+        self.base.tick()
+    }
+
+    /// The tick on which the task's runner first ran it, right after calling
+    /// `TaskComponent::on_unblocked`.
+    pub fn unblocked_tick(&self) -> u64 {
+        self.base.unblocked_tick()
+    }
+
+    /// Time taken by the last tick of the `TaskClock`.
+    pub fn delta_time(&self) -> Duration {
+        self.base.delta_time()
+    }
+
+    /// The part of the context that doesn't need the command buffer, e.g. to implement
+    /// `TaskComponent::run` by calling `ParTaskComponent::par_run`.
+    pub fn without_commands(&mut self) -> &mut ParTaskContext<'a> {
+        &mut self.base
+    }
+
+    /// The command buffer of the system that runs the task.
+    pub fn commands(&mut self) -> &mut CommandBuffer {
+        self.cmd
+    }
+
+    /// Reports how much of its work the task has done, from 0.0 to 1.0. Values outside of that
+    /// range are clamped, and NaN or infinite values count as 0.0. Once the task finishes, its
+    /// progress is 1.0 regardless.
+    pub fn report_progress(&mut self, fraction: f32) {
+        self.base.report_progress(fraction);
+    }
+
+    /// Assembles `graph` as a dependency of the running task, which won't be run again until the
+    /// graph finishes. When it completes, the task gets the graph's output as its next input. When
+    /// it fails or is cancelled, the task finishes the same way, subject to the `OnFailure` of the
+    /// task's own graph. Graphs spawned during the same run run one after another. The task must
+    /// return `TaskStatus::Running` until then: if it finishes anyway, the graph is cancelled, and
+    /// a task that completes fails instead.
+    pub fn spawn_and_await(&mut self, graph: TaskGraph) {
+        let graph = OwnedGraph(graph.remove_nil());
+        if let Cons::Nil = graph.0 {
+            return;
+        }
+        let (first_entity, last_entity) = graph.0._assemble(None, self.cmd);
+        let entity = self.entity();
+        self.cmd.exec_mut(move |world| {
+            // The tasks that the running task already depended on stay reachable through the new
+            // graph, so that they are still deleted or cancelled along with the rest.
+            let edge = world
+                .get_component::<SingleEdge>(entity)
+                .map(|e| (*e).clone());
+            if let Some(edge) = edge {
+                let _ = world.add_component(first_entity, edge);
+            }
+            // Fails if the task was deleted in the meantime.
+            let _ = world.add_component(entity, SingleEdge { child: last_entity });
+        });
+        self.base.progress.await_entity(last_entity);
+        log::debug!("Task {:?} awaits {:?}", entity, last_entity);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::components::TaskOutcome;
+
+use legion::prelude::*;
+
+/// Something that happened to a task graph entity during a run of the task manager system.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TaskEvent {
+    /// The entity's dependencies finished, so it may start running.
+    Unblocked(Entity),
+    /// The entity finished. Sent once for every task and control node, including the ones that
+    /// were cancelled along with their parent.
+    Finished(Entity, TaskOutcome),
+    /// The graph whose final entity is `Entity` finished.
+    GraphFinished(Entity, TaskOutcome),
+    /// The entity was deleted, either by `OnCompletion::Delete` or by a control node that no
+    /// longer needs it.
+    Deleted(Entity),
+}
+
+impl TaskEvent {
+    pub fn entity(&self) -> Entity {
+        match self {
+            TaskEvent::Unblocked(e)
+            | TaskEvent::Finished(e, _)
+            | TaskEvent::GraphFinished(e, _)
+            | TaskEvent::Deleted(e) => *e,
+        }
+    }
+}
+
+/// A resource collecting the `TaskEvent`s of the task manager system created with
+/// `build_task_manager_system_with_events`. Each run adds its events to the ones that haven't been
+/// drained yet, so a reader that runs less often than the task manager doesn't miss any. The
+/// events pile up until they are drained.
+#[derive(Debug, Default)]
+pub struct TaskEvents {
+    pub(crate) events: Vec<TaskEvent>,
+}
+
+impl TaskEvents {
+    pub fn iter(&self) -> impl Iterator<Item = &TaskEvent> {
+        self.events.iter()
+    }
+
+    /// Takes the events, so that other readers won't see them.
+    pub fn drain(&mut self) -> impl Iterator<Item = TaskEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
@@ -0,0 +1,135 @@
+use crate::clock::TaskClock;
+use crate::components::{TaskComponent, TaskError, TaskOutput, TaskStatus};
+use crate::context::TaskContext;
+use crate::runner::{run_tasks, task_runner_query};
+
+use legion::{prelude::*, systems::SystemId};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A boxed future that can be run by a `FutureTask`, for when the type of the future can't be
+/// named, as with `async` blocks.
+pub type BoxedTaskFuture<T> = Pin<Box<dyn Future<Output = Result<T, TaskError>> + Send>>;
+
+// Set by the waker of a `FutureTask`, so that it only gets polled again once it can make progress.
+struct NeedsPoll(AtomicBool);
+
+impl Wake for NeedsPoll {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// A task that polls a future until it resolves. Resolving to `Ok` completes the task with the
+/// value as its output, and resolving to `Err` fails it. The future is made by calling
+/// `make_future` the first time the task runs, so every copy of the task, like the ones assembled
+/// by `retry!` or `repeat!`, gets a fresh future. It is only polled again after its waker has been
+/// woken, and it is dropped if the task is cancelled.
+pub struct FutureTask<F> {
+    make_future: Arc<dyn Fn() -> F + Send + Sync>,
+    // Only accessed through `&mut self`; the lock just makes the task `Sync`.
+    future: Mutex<Option<Pin<Box<F>>>>,
+    needs_poll: Arc<NeedsPoll>,
+    output: Option<TaskOutput>,
+}
+
+impl<F> FutureTask<F> {
+    pub fn new<M>(make_future: M) -> Self
+    where
+        M: 'static + Fn() -> F + Send + Sync,
+    {
+        FutureTask::from_arc(Arc::new(make_future))
+    }
+
+    fn from_arc(make_future: Arc<dyn Fn() -> F + Send + Sync>) -> Self {
+        FutureTask {
+            make_future,
+            future: Mutex::new(None),
+            needs_poll: Arc::new(NeedsPoll(AtomicBool::new(true))),
+            output: None,
+        }
+    }
+}
+
+impl<T: 'static + Any + Send + Sync> FutureTask<BoxedTaskFuture<T>> {
+    /// Boxes the futures made by `make_future`, so that the type of the task can be named.
+    pub fn boxed<M, F>(make_future: M) -> Self
+    where
+        M: 'static + Fn() -> F + Send + Sync,
+        F: 'static + Future<Output = Result<T, TaskError>> + Send,
+    {
+        FutureTask::new(move || -> BoxedTaskFuture<T> { Box::pin(make_future()) })
+    }
+}
+
+// A copy doesn't share the state of the original; it starts over with a new future.
+impl<F> Clone for FutureTask<F> {
+    fn clone(&self) -> Self {
+        FutureTask::from_arc(self.make_future.clone())
+    }
+}
+
+impl<'a, F, T> TaskComponent<'a> for FutureTask<F>
+where
+    F: Future<Output = Result<T, TaskError>> + Send,
+    T: 'static + Any + Send + Sync,
+{
+    type Data = ();
+
+    fn run(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+        if !self.needs_poll.0.swap(false, Ordering::AcqRel) {
+            return TaskStatus::Running;
+        }
+        let make_future = &self.make_future;
+        let future = self
+            .future
+            .get_mut()
+            .unwrap()
+            .get_or_insert_with(|| Box::pin(make_future()));
+        let waker = Waker::from(self.needs_poll.clone());
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Pending => TaskStatus::Running,
+            Poll::Ready(result) => {
+                *self.future.get_mut().unwrap() = None;
+                match result {
+                    Ok(value) => {
+                        self.output = Some(TaskOutput::new(value));
+                        TaskStatus::Complete
+                    }
+                    Err(error) => TaskStatus::Failed(error),
+                }
+            }
+        }
+    }
+
+    fn on_cancel(&mut self) {
+        *self.future.get_mut().unwrap() = None;
+    }
+
+    fn take_output(&mut self) -> Option<TaskOutput> {
+        self.output.take()
+    }
+}
+
+/// Creates a system that runs `FutureTask<F>` tasks. Requires a `TaskClock` resource.
+pub fn build_future_task_runner_system<F, T, I>(id: I) -> Box<dyn Schedulable>
+where
+    F: 'static + Future<Output = Result<T, TaskError>> + Send,
+    T: 'static + Any + Send + Sync,
+    I: Into<SystemId>,
+{
+    SystemBuilder::new(id)
+        .read_resource::<TaskClock>()
+        .with_query(task_runner_query::<FutureTask<F>>())
+        .build(|cmd, world, clock, task_query| run_tasks(cmd, world, clock, &mut (), task_query))
+}
@@ -1,6 +1,8 @@
 use crate::components::*;
+use crate::handle::TaskHandle;
 
 use legion::prelude::*;
+use std::sync::Arc;
 
 /// Implemented by all nodes of a `TaskGraph`. Has a blanket impl that should work for most
 /// `TaskComponent`s.
@@ -24,86 +26,360 @@ impl<'a, T: 'static + Clone + TaskComponent<'a>> TaskFactory for T {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Cons<T> {
     Fork(Box<Cons<T>>, Box<Cons<T>>),
+    Race(Box<Cons<T>>, Box<Cons<T>>),
+    Quorum(usize, OnQuorum, Vec<Cons<T>>),
     Seq(Box<Cons<T>>, Box<Cons<T>>),
+    // The sub-graph is shared because it gets assembled again on every attempt. There must be at
+    // least one attempt, which `retry!` checks when the graph is built.
+    Retry(u32, Arc<Cons<T>>),
+    Timeout(Timeout, OnTimeout, Box<Cons<T>>),
+    // Both sides are shared because only one of them gets assembled, once the branch is unblocked.
+    Branch(Predicate, Arc<Cons<T>>, Arc<Cons<T>>),
+    // Loops are shared because they get assembled again on every iteration.
+    Repeat(u32, Arc<Cons<T>>),
+    While(Predicate, Arc<Cons<T>>),
     Task(T),
     Nil, // currently required to support graph accumulation
 }
 
 impl<T> Cons<T> {
-    fn remove_nil(self) -> Self {
+    pub(crate) fn remove_nil(self) -> Self {
+        // Graphs accumulated in a loop can be too deep to recurse through, so nodes wait on a stack
+        // until their children have been reduced, along with how many children they had.
+        let mut stack = vec![(self, None)];
+        let mut reduced = Vec::new();
+        while let Some((mut node, num_children)) = stack.pop() {
+            match num_children {
+                // Children are reduced first, since they might turn out to be empty.
+                None => {
+                    let mut children = Vec::new();
+                    node.take_children(&mut children);
+                    stack.push((node, Some(children.len())));
+                    stack.extend(children.into_iter().rev().map(|child| (child, None)));
+                }
+                Some(n) => {
+                    let children = reduced.split_off(reduced.len() - n);
+                    reduced.push(node.restore_children(children));
+                }
+            }
+        }
+
+        reduced.pop().expect("Every node is reduced to one node")
+    }
+
+    /// Moves the children that `self` owns outright onto `children`, leaving nils in their place.
+    fn take_children(&mut self, children: &mut Vec<Cons<T>>) {
         match self {
-            Cons::Seq(head, tail) => match (*head, *tail) {
-                (Cons::Nil, Cons::Nil) => Cons::Nil,
-                (Cons::Nil, t) => t.remove_nil(),
-                (h, Cons::Nil) => h.remove_nil(),
-                (h, t) => Cons::Seq(Box::new(h.remove_nil()), Box::new(t.remove_nil())),
-            },
-            Cons::Fork(head, tail) => match (*head, *tail) {
-                (Cons::Nil, Cons::Nil) => Cons::Nil,
-                (Cons::Nil, t) => t.remove_nil(),
-                (h, Cons::Nil) => h.remove_nil(),
-                (h, t) => Cons::Fork(Box::new(h.remove_nil()), Box::new(t.remove_nil())),
-            },
-            Cons::Task(t) => Cons::Task(t),
-            Cons::Nil => Cons::Nil,
+            Cons::Seq(head, tail) | Cons::Fork(head, tail) | Cons::Race(head, tail) => {
+                children.push(std::mem::replace(head, Cons::Nil));
+                children.push(std::mem::replace(tail, Cons::Nil));
+            }
+            Cons::Quorum(_, _, prongs) => children.append(prongs),
+            Cons::Timeout(_, _, graph) => children.push(std::mem::replace(graph, Cons::Nil)),
+            _ => (),
+        }
+    }
+
+    /// Puts back the children taken by `take_children`, once nils have been removed from them, and
+    /// removes nils from shared sub-graphs. Returns what is left of `self`.
+    fn restore_children(mut self, mut children: Vec<Cons<T>>) -> Self {
+        match &mut self {
+            Cons::Seq(head, tail) | Cons::Fork(head, tail) | Cons::Race(head, tail) => {
+                let t = children.pop().expect("Binary node must have a tail");
+                let h = children.pop().expect("Binary node must have a head");
+                match (h, t) {
+                    (Cons::Nil, t) => t,
+                    (h, Cons::Nil) => h,
+                    (h, t) => {
+                        **head = h;
+                        **tail = t;
+                        self
+                    }
+                }
+            }
+            Cons::Quorum(needed, _, prongs) => {
+                // Empty prongs are dropped, as they are from forks and races, so they never count
+                // towards the quorum. A quorum left with fewer prongs than it needs needs them all.
+                prongs.extend(children.into_iter().filter(|p| !matches!(p, Cons::Nil)));
+                *needed = (*needed).min(prongs.len());
+                if prongs.is_empty() {
+                    Cons::Nil
+                } else {
+                    self
+                }
+            }
+            Cons::Timeout(_, _, graph) => {
+                match children.pop().expect("Timeout must have a graph") {
+                    Cons::Nil => Cons::Nil,
+                    g => {
+                        **graph = g;
+                        self
+                    }
+                }
+            }
+            Cons::Branch(_, then_graph, else_graph) => {
+                let is_then_empty = remove_shared_nil(then_graph);
+                let is_else_empty = remove_shared_nil(else_graph);
+                // An empty side is kept, since choosing it still means skipping the other.
+                if is_then_empty && is_else_empty {
+                    Cons::Nil
+                } else {
+                    self
+                }
+            }
+            Cons::Repeat(0, _) => {
+                self.dismantle();
+                Cons::Nil
+            }
+            Cons::Retry(_, graph) | Cons::Repeat(_, graph) | Cons::While(_, graph) => {
+                if remove_shared_nil(graph) {
+                    Cons::Nil
+                } else {
+                    self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// Like `take_children`, but for sub-graphs that are shared with nothing else.
+    fn take_unshared_graphs(&mut self, graphs: &mut Vec<Cons<T>>) {
+        let mut take = |graph: &mut Arc<Cons<T>>| {
+            if let Some(g) = Arc::get_mut(graph) {
+                graphs.push(std::mem::replace(g, Cons::Nil));
+            }
+        };
+        match self {
+            Cons::Retry(_, graph) | Cons::Repeat(_, graph) | Cons::While(_, graph) => take(graph),
+            Cons::Branch(_, then_graph, else_graph) => {
+                take(then_graph);
+                take(else_graph);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<T> Cons<T> {
+    /// Detaches the descendents of `self` and drops them one at a time. Dropping a graph the usual
+    /// way recurses once per level of the tree, which a graph accumulated in a loop has too many
+    /// of. Sub-graphs that are shared with something else are left to their other owners.
+    fn dismantle(&mut self) {
+        let mut descendents = Vec::new();
+        self.take_children(&mut descendents);
+        self.take_unshared_graphs(&mut descendents);
+        while let Some(mut node) = descendents.pop() {
+            node.take_children(&mut descendents);
+            node.take_unshared_graphs(&mut descendents);
+        }
+    }
+}
+
+/// A graph that the crate took ownership of, which is dismantled when dropped. `Cons` doesn't do
+/// this in a `Drop` of its own, since that would keep users from moving its fields out in a
+/// `match`.
+pub(crate) struct OwnedGraph<T>(pub(crate) Cons<T>);
+
+impl<T> Drop for OwnedGraph<T> {
+    fn drop(&mut self) {
+        self.0.dismantle();
+    }
+}
+
+/// A sub-graph kept by a control entity, to be assembled once the entity is unblocked. Like an
+/// `OwnedGraph`, it is dismantled when its last owner drops it.
+#[derive(Clone)]
+pub(crate) struct SharedGraph(pub(crate) Arc<TaskGraph>);
+
+impl Drop for SharedGraph {
+    fn drop(&mut self) {
+        if let Some(graph) = Arc::get_mut(&mut self.0) {
+            graph.dismantle();
+        }
+    }
+}
+
+impl std::ops::Deref for SharedGraph {
+    type Target = TaskGraph;
+
+    fn deref(&self) -> &TaskGraph {
+        &self.0
+    }
+}
+
+/// Removes nils from a shared graph in place, returning `true` if nothing is left.
+fn remove_shared_nil<T>(graph: &mut Arc<Cons<T>>) -> bool {
+    match Arc::get_mut(graph) {
+        Some(g) => {
+            *g = std::mem::replace(g, Cons::Nil).remove_nil();
+            matches!(g, Cons::Nil)
         }
+        // Nils can't be removed from a graph that is shared elsewhere.
+        None => false,
     }
 }
 
 /// A node of the binary tree grammar that describes a task graph. `Cons::Seq` lists represent
-/// sequential execution of tasks. `Cons::Fork` lists represent concurrent execution of tasks. The
-/// leaves of the tree are `Cons::Task`s.
+/// sequential execution of tasks. `Cons::Fork` lists represent concurrent execution of tasks, and
+/// `Cons::Race` lists represent concurrent execution where only the first to complete matters.
+/// `Cons::Quorum` is the only node with more than two children, because nested quorums can't be
+/// flattened like nested forks. `Cons::Branch` picks one of two graphs at runtime, and
+/// `Cons::Repeat` and `Cons::While` run a graph in a loop. The leaves of the tree are
+/// `Cons::Task`s.
 pub type TaskGraph = Cons<Box<dyn TaskFactory + Send + Sync>>;
 
-impl Cons<Box<dyn TaskFactory + Send + Sync>> {
-    fn _assemble(self, fork: Option<Entity>, cmd: &mut CommandBuffer) -> (Entity, Entity) {
-        match self {
-            Cons::Seq(head, tail) => {
-                let (head_first_entity, head_last_entity) = head._assemble(None, cmd);
-                let (tail_first_entity, tail_last_entity) = tail._assemble(None, cmd);
-                join(cmd, tail_first_entity, head_last_entity);
+/// What is left to do while assembling a graph.
+enum AssemblyStep<'g> {
+    // Creates the entities of a sub-graph, whose first and last entities become the next result.
+    Assemble(&'g TaskGraph, Option<(Entity, Join)>),
+    // Joins the last two results into a sequence.
+    Seq,
+    // Adds the last results as prongs of a fork entity.
+    AddProngs(Entity, usize),
+    // Makes the last result the inner graph of a timeout.
+    Timeout(Timeout, OnTimeout),
+    Assembled(Entity, Entity),
+}
 
-                (head_first_entity, tail_last_entity)
+impl Cons<Box<dyn TaskFactory + Send + Sync>> {
+    /// Creates the entities of a graph with nils removed, returning the first and last entities.
+    pub(crate) fn _assemble(
+        &self,
+        fork: Option<(Entity, Join)>,
+        cmd: &mut CommandBuffer,
+    ) -> (Entity, Entity) {
+        // Like `remove_nil`, this doesn't recurse. Steps wait on a stack, and the first and last
+        // entities of each assembled sub-graph on another. Steps are pushed in reverse, since the
+        // last one pushed is taken first.
+        let mut steps = vec![AssemblyStep::Assemble(self, fork)];
+        let mut assembled = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                AssemblyStep::Assemble(graph, fork) => {
+                    graph.push_assembly_steps(fork, cmd, &mut steps, &mut assembled)
+                }
+                AssemblyStep::Seq => {
+                    let (tail_first_entity, tail_last_entity) = assembled.pop().unwrap();
+                    let (head_first_entity, head_last_entity) = assembled.pop().unwrap();
+                    join(cmd, tail_first_entity, head_last_entity);
+                    assembled.push((head_first_entity, tail_last_entity));
+                }
+                AssemblyStep::AddProngs(fork_entity, num_prongs) => {
+                    let prongs = assembled.split_off(assembled.len() - num_prongs);
+                    // Any decendents reachable only via Cons::Fork are considered prongs. If a
+                    // descendent is a Cons::Seq, then the prong only connects at the "last" entity
+                    // of the sequence.
+                    for (_, prong_last_entity) in prongs.into_iter() {
+                        if prong_last_entity != fork_entity {
+                            add_prong(cmd, fork_entity, prong_last_entity);
+                        }
+                    }
+                }
+                AssemblyStep::Timeout(timeout, on_timeout) => {
+                    let (_, inner_last_entity) = assembled.pop().unwrap();
+                    let timeout_entity = make_timeout(cmd, inner_last_entity, timeout, on_timeout);
+                    assembled.push((timeout_entity, timeout_entity));
+                }
+                AssemblyStep::Assembled(first_entity, last_entity) => {
+                    assembled.push((first_entity, last_entity))
+                }
             }
-            Cons::Fork(head, tail) => {
-                let fork_entity = if let Some(e) = fork {
-                    e
-                } else {
-                    make_fork(cmd)
-                };
+        }
 
-                let (_, head_last_entity) = head._assemble(Some(fork_entity), cmd);
-                let (_, tail_last_entity) = tail._assemble(Some(fork_entity), cmd);
+        assembled.pop().expect("Assembling a graph has one result")
+    }
 
-                // Any decendents reachable only via Cons::Fork are considered prongs. If a
-                // descendent is a Cons::Seq, then the prong only connects at the "last" entity of
-                // the sequence.
-                if head_last_entity != fork_entity {
-                    add_prong(cmd, fork_entity, head_last_entity);
-                }
-                if tail_last_entity != fork_entity {
-                    add_prong(cmd, fork_entity, tail_last_entity);
+    /// Creates the entity of `self` if it has one, and pushes the steps that assemble its children.
+    /// Leaves are assembled right away.
+    fn push_assembly_steps<'g>(
+        &'g self,
+        fork: Option<(Entity, Join)>,
+        cmd: &mut CommandBuffer,
+        steps: &mut Vec<AssemblyStep<'g>>,
+        assembled: &mut Vec<(Entity, Entity)>,
+    ) {
+        let entity = match self {
+            Cons::Seq(head, tail) => {
+                steps.push(AssemblyStep::Seq);
+                steps.push(AssemblyStep::Assemble(tail, None));
+                steps.push(AssemblyStep::Assemble(head, None));
+                return;
+            }
+            Cons::Fork(head, tail) | Cons::Race(head, tail) => {
+                let join = match self {
+                    Cons::Fork(..) => Join::All,
+                    _ => Join::RACE,
+                };
+                // Nested forks of the same kind share a single entity.
+                let fork_entity = match fork {
+                    Some((e, fork_join)) if fork_join == join => e,
+                    _ => match join {
+                        Join::All => make_fork(cmd),
+                        Join::Quorum { needed, rest } => make_quorum(cmd, needed, rest),
+                    },
+                };
+                steps.push(AssemblyStep::Assembled(fork_entity, fork_entity));
+                steps.push(AssemblyStep::AddProngs(fork_entity, 2));
+                steps.push(AssemblyStep::Assemble(tail, Some((fork_entity, join))));
+                steps.push(AssemblyStep::Assemble(head, Some((fork_entity, join))));
+                return;
+            }
+            Cons::Quorum(needed, on_quorum, prongs) => {
+                let quorum_entity = make_quorum(cmd, *needed, *on_quorum);
+                steps.push(AssemblyStep::Assembled(quorum_entity, quorum_entity));
+                for prong in prongs.iter().rev() {
+                    steps.push(AssemblyStep::AddProngs(quorum_entity, 1));
+                    steps.push(AssemblyStep::Assemble(prong, None));
                 }
-
-                (fork_entity, fork_entity)
+                return;
             }
-            Cons::Task(task) => {
-                let task_entity = task.create_task(cmd);
-
-                (task_entity, task_entity)
+            Cons::Timeout(timeout, on_timeout, graph) => {
+                steps.push(AssemblyStep::Timeout(*timeout, *on_timeout));
+                steps.push(AssemblyStep::Assemble(graph, None));
+                return;
             }
+            Cons::Retry(attempts, graph) => make_retry(cmd, graph.clone(), *attempts),
+            Cons::Branch(predicate, then_graph, else_graph) => make_branch(
+                cmd,
+                predicate.clone(),
+                then_graph.clone(),
+                else_graph.clone(),
+            ),
+            Cons::Repeat(iterations, graph) => make_repeat(cmd, graph.clone(), *iterations),
+            Cons::While(predicate, graph) => make_while(cmd, predicate.clone(), graph.clone()),
+            Cons::Task(task) => task.create_task(cmd),
             Cons::Nil => panic!("Tried to assemble Cons::Nil, which should always be removed."),
-        }
+        };
+        assembled.push((entity, entity));
     }
 
     /// Mark the root of the `TaskGraph` as final, effectively unblocking the first tasks in this
-    /// graph to be run. Panics if `self` contains no tasks.
-    pub fn assemble(self, on_completion: OnCompletion, cmd: &mut CommandBuffer) -> Entity {
-        let s = self.remove_nil();
-        let (_first_entity, last_entity) = s._assemble(None, cmd);
-        finalize(cmd, last_entity, on_completion);
+    /// graph to be run. Returns a handle for checking on the graph. Panics if `self` contains no
+    /// tasks.
+    pub fn assemble(
+        self,
+        on_completion: OnCompletion,
+        on_failure: OnFailure,
+        cmd: &mut CommandBuffer,
+    ) -> TaskHandle {
+        let s = OwnedGraph(self.remove_nil());
+        let (_first_entity, last_entity) = s.0._assemble(None, cmd);
+
+        finalize(cmd, last_entity, on_completion, on_failure)
+    }
 
-        last_entity
+    /// Like `assemble`, but also calls one of `callbacks` once the graph finishes.
+    pub fn assemble_with_callbacks(
+        self,
+        on_completion: OnCompletion,
+        on_failure: OnFailure,
+        callbacks: GraphCallbacks,
+        cmd: &mut CommandBuffer,
+    ) -> TaskHandle {
+        let handle = self.assemble(on_completion, on_failure, cmd);
+        set_callbacks(cmd, handle.entity(), callbacks);
+
+        handle
     }
 }
 
@@ -143,6 +419,167 @@ macro_rules! fork {
     ($head:expr) => ( $head );
 }
 
+/// Returns a `TaskGraph` that runs a `TaskGraph` again, as a fresh copy, each time it fails. The
+/// graph is run at most `attempts` times before the failure is passed on to whatever depends on it.
+/// Panics if `attempts` is 0: unlike `repeat!(0, graph)`, which is empty and completes right away,
+/// a graph that is never attempted has no outcome to pass on.
+#[macro_export]
+macro_rules! retry {
+    ($attempts:expr, @$task:expr) => {
+        retry!($attempts, Cons::Task(Box::new($task)))
+    };
+    ($attempts:expr, $graph:expr) => {{
+        let attempts: u32 = $attempts;
+        assert!(attempts > 0, "retry! needs at least one attempt");
+        Cons::Retry(attempts, std::sync::Arc::new($graph))
+    }};
+}
+
+/// Returns a `TaskGraph` that runs a `TaskGraph` `iterations` times in a row, as a fresh copy each
+/// time.
+#[macro_export]
+macro_rules! repeat {
+    ($iterations:expr, @$task:expr) => {
+        Cons::Repeat(
+            $iterations,
+            std::sync::Arc::new(Cons::Task(Box::new($task))),
+        )
+    };
+    ($iterations:expr, $graph:expr) => {
+        Cons::Repeat($iterations, std::sync::Arc::new($graph))
+    };
+}
+
+/// Returns a `TaskGraph` that runs a `TaskGraph` again, as a fresh copy, for as long as a predicate
+/// over the `World` holds. The predicate is evaluated before each iteration. Prefix the predicate
+/// with `resources:` to also give it the `Resources`, as for `if_else!`.
+#[macro_export]
+macro_rules! while_ {
+    (resources: $predicate:expr, @$task:expr) => {
+        Cons::While(
+            Predicate::with_resources($predicate),
+            std::sync::Arc::new(Cons::Task(Box::new($task))),
+        )
+    };
+    (resources: $predicate:expr, $graph:expr) => {
+        Cons::While(
+            Predicate::with_resources($predicate),
+            std::sync::Arc::new($graph),
+        )
+    };
+    ($predicate:expr, @$task:expr) => {
+        Cons::While(
+            Predicate::new($predicate),
+            std::sync::Arc::new(Cons::Task(Box::new($task))),
+        )
+    };
+    ($predicate:expr, $graph:expr) => {
+        Cons::While(Predicate::new($predicate), std::sync::Arc::new($graph))
+    };
+}
+
+/// Returns a `TaskGraph` that stops a `TaskGraph` if it hasn't finished within a `Timeout` of being
+/// unblocked. Wrap a whole graph to give it an overall deadline.
+#[macro_export]
+macro_rules! timeout {
+    ($timeout:expr, $on_timeout:expr, @$task:expr) => {
+        Cons::Timeout($timeout, $on_timeout, Box::new(Cons::Task(Box::new($task))))
+    };
+    ($timeout:expr, $on_timeout:expr, $graph:expr) => {
+        Cons::Timeout($timeout, $on_timeout, Box::new($graph))
+    };
+}
+
+/// Returns a `TaskGraph` that runs one of two `TaskGraph`s, depending on a predicate over the
+/// `World` that is evaluated when the branch is unblocked. Only the chosen graph is assembled. With
+/// `resources:` before it, the predicate also gets the `Resources`, and is evaluated by
+/// `evaluate_task_predicates`.
+#[macro_export]
+macro_rules! if_else {
+    (resources: $predicate:expr, $($sides:tt)*) => {
+        if_else!(@with Predicate::with_resources($predicate), $($sides)*)
+    };
+    (@with $predicate:expr, @$then:expr, @$else:expr) => {
+        if_else!(
+            @with $predicate,
+            Cons::Task(Box::new($then)),
+            Cons::Task(Box::new($else))
+        )
+    };
+    (@with $predicate:expr, @$then:expr, $else:expr) => {
+        if_else!(@with $predicate, Cons::Task(Box::new($then)), $else)
+    };
+    (@with $predicate:expr, $then:expr, @$else:expr) => {
+        if_else!(@with $predicate, $then, Cons::Task(Box::new($else)))
+    };
+    (@with $predicate:expr, $then:expr, $else:expr) => {
+        Cons::Branch(
+            $predicate,
+            std::sync::Arc::new($then),
+            std::sync::Arc::new($else),
+        )
+    };
+    ($predicate:expr, $($sides:tt)*) => {
+        if_else!(@with Predicate::new($predicate), $($sides)*)
+    };
+}
+
+/// Returns a `TaskGraph` that executes the argument list of `TaskGraphs` concurrently and completes
+/// as soon as one of them completes. The rest are cancelled. Empty prongs are dropped rather than
+/// winning the race.
+#[macro_export]
+macro_rules! race {
+    (@$head:expr, $($tail:tt)*) => (
+        Cons::Race(Box::new(race!(@$head)), Box::new(race!($($tail)*)))
+    );
+    ($head:expr, $($tail:tt)*) => (
+        Cons::Race(Box::new(race!($head)), Box::new(race!($($tail)*)))
+    );
+    (@$task:expr) => (
+        Cons::Task(Box::new($task))
+    );
+    ($head:expr) => ( $head );
+}
+
+/// Returns a `TaskGraph` that executes the argument list of `TaskGraphs` concurrently and completes
+/// as soon as `needed` of them complete. The rest are handled according to an `OnQuorum`. Like for
+/// `race!`, empty prongs are dropped and don't count as complete; if that leaves fewer prongs than
+/// `needed`, all of them are needed. Panics if
+/// `needed` is 0 or more than the number of prongs, since the graph may only be assembled later, by
+/// the task manager, if it's nested in a retry, loop or branch.
+#[macro_export]
+macro_rules! quorum {
+    (@prongs [$($done:expr),*]) => (
+        vec![$($done),*]
+    );
+    (@prongs [$($done:expr),*] @$task:expr $(, $($tail:tt)*)?) => (
+        quorum!(@prongs [$($done,)* Cons::Task(Box::new($task))] $($($tail)*)?)
+    );
+    (@prongs [$($done:expr),*] $graph:expr $(, $($tail:tt)*)?) => (
+        quorum!(@prongs [$($done,)* $graph] $($($tail)*)?)
+    );
+    (@count [$($done:tt),*]) => (
+        <[()]>::len(&[$($done),*])
+    );
+    (@count [$($done:tt),*] @$task:expr $(, $($tail:tt)*)?) => (
+        quorum!(@count [$($done,)* ()] $($($tail)*)?)
+    );
+    (@count [$($done:tt),*] $graph:expr $(, $($tail:tt)*)?) => (
+        quorum!(@count [$($done,)* ()] $($($tail)*)?)
+    );
+    ($needed:expr, $on_quorum:expr, $($prongs:tt)*) => {{
+        let needed: usize = $needed;
+        let num_prongs = quorum!(@count [] $($prongs)*);
+        assert!(
+            needed > 0 && needed <= num_prongs,
+            "quorum! needs between 1 and {} prongs, not {}",
+            num_prongs,
+            needed
+        );
+        Cons::Quorum(needed, $on_quorum, quorum!(@prongs [] $($prongs)*))
+    }};
+}
+
 /// Returns a `TaskGraph` that executes the argument list of `TaskGraphs` sequentially.
 #[macro_export]
 macro_rules! seq {
@@ -270,4 +707,161 @@ mod tests {
             seq!(seq!(seq!(@Foo(0), @Foo(1)), @Foo(2)), @Foo(3))
         );
     }
+
+    #[test]
+    fn remove_nil_from_very_long_sequence() {
+        let mut s = empty_graph!();
+        for i in 0..100_000 {
+            s = seq!(s, @Foo(i));
+        }
+        let s = OwnedGraph(s.remove_nil());
+        let mut depth = 0;
+        let mut first = &s.0;
+        while let Cons::Seq(head, _) = first {
+            depth += 1;
+            first = head;
+        }
+        assert_eq!(depth, 99_999);
+        assert_eq!(first, &task!(@Foo(0)));
+    }
+
+    #[test]
+    fn fields_can_be_moved_out_of_cons() {
+        let x = seq!(@Foo(1), @Foo(2));
+        let head = match x {
+            Cons::Seq(head, _tail) => head,
+            _ => unreachable!(),
+        };
+        assert_eq!(*head, task!(@Foo(1)));
+    }
+
+    #[test]
+    fn remove_nil_inside_retry() {
+        let x = retry!(2, seq!(Cons::Nil, @Foo(1)));
+        assert_eq!(x.remove_nil(), retry!(2, @Foo(1)));
+    }
+
+    #[test]
+    fn retry_of_nil_is_nil() {
+        let x = seq!(@Foo(1), retry!(2, Cons::Nil));
+        assert_eq!(x.remove_nil(), task!(@Foo(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "retry! needs at least one attempt")]
+    fn retry_without_attempts_is_rejected() {
+        let _ = retry!(0, @Foo(1));
+    }
+
+    #[test]
+    fn timeout_of_nil_is_nil() {
+        let x = seq!(timeout!(Timeout::Ticks(1), OnTimeout::Fail, Cons::Nil), @Foo(1));
+        assert_eq!(x.remove_nil(), task!(@Foo(1)));
+    }
+
+    #[test]
+    fn task_graph_macro_race_three() {
+        let x = race!(@Foo(1u32), @Foo(2u32), @Foo(3u32));
+        assert_eq!(
+            x,
+            Cons::Race(
+                Box::new(Cons::Task(Box::new(Foo(1u32)))),
+                Box::new(Cons::Race(
+                    Box::new(Cons::Task(Box::new(Foo(2u32)))),
+                    Box::new(Cons::Task(Box::new(Foo(3u32)))),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn task_graph_macro_quorum() {
+        let x = quorum!(2, OnQuorum::CancelRest, @Foo(1u32), task!(@Foo(2u32)), @Foo(3u32));
+        assert_eq!(
+            x,
+            Cons::Quorum(
+                2,
+                OnQuorum::CancelRest,
+                vec![
+                    Cons::Task(Box::new(Foo(1u32))),
+                    Cons::Task(Box::new(Foo(2u32))),
+                    Cons::Task(Box::new(Foo(3u32))),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "quorum! needs between 1 and 2 prongs, not 3")]
+    fn quorum_needing_too_many_prongs_is_rejected() {
+        let _ = quorum!(3, OnQuorum::CancelRest, @Foo(1), @Foo(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "quorum! needs between 1 and 2 prongs, not 0")]
+    fn quorum_needing_no_prongs_is_rejected() {
+        let _ = quorum!(0, OnQuorum::CancelRest, @Foo(1), @Foo(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "quorum! needs between 1 and 2 prongs, not 3")]
+    fn quorum_nested_in_retry_is_rejected_when_built() {
+        let _ = retry!(2, quorum!(3, OnQuorum::CancelRest, @Foo(1), @Foo(2)));
+    }
+
+    #[test]
+    fn remove_nil_drops_empty_race_prong() {
+        let x = race!(@Foo(1), Cons::Nil);
+        assert_eq!(x.remove_nil(), task!(@Foo(1)));
+    }
+
+    #[test]
+    fn remove_nil_drops_empty_quorum_prong() {
+        let x = quorum!(2, OnQuorum::LeaveRunning, Cons::Nil, @Foo(1), @Foo(2));
+        assert_eq!(
+            x.remove_nil(),
+            quorum!(2, OnQuorum::LeaveRunning, @Foo(1), @Foo(2))
+        );
+    }
+
+    #[test]
+    fn remove_nil_from_quorum_needs_all_prongs_left() {
+        let x = quorum!(2, OnQuorum::CancelRest, @Foo(1), Cons::Nil);
+        assert_eq!(x.remove_nil(), quorum!(1, OnQuorum::CancelRest, @Foo(1)));
+    }
+
+    #[test]
+    fn remove_nil_keeps_branch_with_one_empty_side() {
+        let x: Cons<Box<Foo>> = if_else!(|_| true, Cons::Nil, seq!(Cons::Nil, @Foo(1)));
+        let predicate = match &x {
+            Cons::Branch(p, _, _) => p.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            x.remove_nil(),
+            Cons::Branch(
+                predicate,
+                Arc::new(Cons::Nil),
+                Arc::new(Cons::Task(Box::new(Foo(1)))),
+            )
+        );
+    }
+
+    #[test]
+    fn branch_of_nils_is_nil() {
+        let x: Cons<Box<Foo>> = if_else!(|_| true, Cons::Nil, Cons::Nil);
+        assert_eq!(x.remove_nil(), Cons::Nil);
+    }
+
+    #[test]
+    fn repeat_zero_times_is_nil() {
+        let x = repeat!(0, @Foo(1));
+        assert_eq!(x.remove_nil(), Cons::Nil);
+    }
+
+    #[test]
+    fn while_of_nil_is_nil() {
+        let x: Cons<Box<Foo>> = while_!(|_| true, seq!(Cons::Nil, Cons::Nil));
+        assert_eq!(x.remove_nil(), Cons::Nil);
+    }
 }
@@ -0,0 +1,112 @@
+use crate::components::{TaskOutcome, TaskOutput, TaskProgress};
+use crate::manager::graph_progress;
+
+use legion::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// How a graph finished, recorded by the task manager so that it can still be read after the
+/// graph is deleted.
+#[derive(Clone, Debug)]
+pub(crate) struct FinishedGraph {
+    pub(crate) outcome: TaskOutcome,
+    pub(crate) output: Option<TaskOutput>,
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct HandleSlot(pub(crate) Arc<Mutex<GraphState>>);
+
+/// What handles know about their graph, shared with the final entity.
+pub(crate) struct GraphState {
+    // Changes when work is appended to the graph.
+    final_entity: Entity,
+    finished: Option<FinishedGraph>,
+}
+
+impl HandleSlot {
+    pub(crate) fn new(final_entity: Entity) -> Self {
+        HandleSlot(Arc::new(Mutex::new(GraphState {
+            final_entity,
+            finished: None,
+        })))
+    }
+
+    pub(crate) fn finish(&self, finished: FinishedGraph) {
+        self.0.lock().unwrap().finished = Some(finished);
+    }
+
+    pub(crate) fn move_to(&self, final_entity: Entity) {
+        self.0.lock().unwrap().final_entity = final_entity;
+    }
+}
+
+/// Refers to a finalized task graph. Returned by `TaskGraph::assemble` and `finalize`. Unlike
+/// `entity_outcome`, it can be queried from a plain `&World`, outside of any system, and it keeps
+/// working after the graph has been deleted by `OnCompletion::Delete`.
+#[derive(Clone)]
+pub struct TaskHandle {
+    slot: HandleSlot,
+}
+
+impl TaskHandle {
+    pub(crate) fn new(slot: HandleSlot) -> Self {
+        TaskHandle { slot }
+    }
+
+    /// The final entity of the graph. This changes when another graph is appended to it.
+    pub fn entity(&self) -> Entity {
+        self.slot.0.lock().unwrap().final_entity
+    }
+
+    /// How the graph finished, or `None` if it hasn't finished yet.
+    pub fn outcome(&self, world: &World) -> Option<TaskOutcome> {
+        if let Some(finished) = self.finished() {
+            return Some(finished.outcome);
+        }
+        // The final entity may not exist yet if the command buffer hasn't been flushed.
+        world
+            .get_component::<TaskProgress>(self.entity())
+            .and_then(|progress| progress.outcome())
+    }
+
+    /// Returns `true` iff the graph has finished, whether it completed or not.
+    pub fn is_finished(&self, world: &World) -> bool {
+        self.outcome(world).is_some()
+    }
+
+    /// Returns `true` iff the graph has completed.
+    pub fn is_complete(&self, world: &World) -> bool {
+        self.outcome(world) == Some(TaskOutcome::Complete)
+    }
+
+    /// The output of the final entity, once the graph has completed.
+    pub fn output(&self, world: &World) -> Option<TaskOutput> {
+        if let Some(finished) = self.finished() {
+            return finished.output;
+        }
+        world
+            .get_component::<TaskProgress>(self.entity())
+            .and_then(|progress| progress.output())
+    }
+
+    /// How far along the graph is, from 0.0 to 1.0, as reported by `graph_progress`.
+    pub fn progress(&self, world: &World) -> f32 {
+        if self.finished().is_some() {
+            1.0
+        } else {
+            graph_progress(world, self.entity())
+        }
+    }
+
+    fn finished(&self) -> Option<FinishedGraph> {
+        self.slot.0.lock().unwrap().finished.clone()
+    }
+}
+
+impl std::fmt::Debug for TaskHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TaskHandle")
+            .field("entity", &self.entity())
+            .finish()
+    }
+}
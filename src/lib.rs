@@ -15,10 +15,10 @@
 //! impl<'a> TaskComponent<'a> for SaySomething {
 //!     type Data = ();
 //!
-//!     fn run(&mut self, data: &mut Self::Data) -> bool {
+//!     fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
 //!         println!("{}", self.0);
 //!
-//!         true
+//!         TaskStatus::Complete
 //!     }
 //! }
 //!
@@ -30,10 +30,10 @@
 //! impl<'a> TaskComponent<'a> for PushValue {
 //!     type Data = Vec<usize>;
 //!
-//!     fn run(&mut self, data: &mut Self::Data) -> bool {
+//!     fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
 //!         data.push(self.value);
 //!
-//!         true
+//!         TaskStatus::Complete
 //!     }
 //! }
 //!
@@ -48,7 +48,7 @@
 //!         ),
 //!         @SaySomething("goodbye")
 //!     );
-//!     task_graph.assemble(OnCompletion::Delete, cmd);
+//!     task_graph.assemble(OnCompletion::Delete, OnFailure::Abort, cmd);
 //! }
 //!
 //! fn make_dynamic_task_graph(cmd: &mut CommandBuffer) {
@@ -59,28 +59,31 @@
 //!     }
 //!     let last: TaskGraph = task!(@SaySomething("goodbye"));
 //!     let task_graph: TaskGraph = seq!(first, middle, last);
-//!     task_graph.assemble(OnCompletion::Delete, cmd);
+//!     task_graph.assemble(OnCompletion::Delete, OnFailure::Abort, cmd);
 //! }
 //!
 //! fn build_say_something_task_runner_system() -> Box<dyn Schedulable> {
 //!     SystemBuilder::new("say_something_task_runner")
+//!         .read_resource::<TaskClock>()
 //!         .with_query(task_runner_query::<SaySomething>())
-//!         .build(|_, mut world, _, task_query| {
-//!             run_tasks(&mut world, &mut (), task_query)
+//!         .build(|cmd, mut world, clock, task_query| {
+//!             run_tasks(cmd, &mut world, clock, &mut (), task_query)
 //!         })
 //! }
 //!
 //! fn build_push_value_task_runner_system() -> Box<dyn Schedulable> {
 //!     SystemBuilder::new("push_value_task_runner")
+//!         .read_resource::<TaskClock>()
 //!         .write_resource::<Vec<usize>>()
 //!         .with_query(task_runner_query::<PushValue>())
-//!         .build(|_, mut world, value, task_query| {
-//!             run_tasks(&mut world, &mut **value, task_query)
+//!         .build(|cmd, mut world, (clock, value), task_query| {
+//!             run_tasks(cmd, &mut world, clock, &mut **value, task_query)
 //!         })
 //! }
 //!
 //! fn make_schedule() -> Schedule {
 //!     Schedule::builder()
+//!         .add_system(build_task_clock_system("task_clock"))
 //!         .add_system(build_say_something_task_runner_system())
 //!         .add_system(build_push_value_task_runner_system())
 //!         .add_system(build_task_manager_system("task_manager"))
@@ -96,7 +99,9 @@
 //! In this model, every task is some entity. The entity is allowed to have exactly one component
 //! that implements `TaskComponent` (it may have other components that don't implement
 //! `TaskComponent`). The task will be run to completion by running a system that calls `run_tasks`
-//! with the proper `TaskComponent::Data` and `task_query`.
+//! with the proper `TaskComponent::Data` and `task_query`. The query created by `task_runner_query`
+//! only matches the tasks that the task manager has unblocked, so the cost of a runner doesn't grow
+//! with the number of tasks that are waiting.
 //!
 //! Every task entity is also a node in a (hopefully acyclic) directed graph. An edge `t2 --> t1`
 //! means that `t2` cannot start until `t1` has completed.
@@ -175,22 +180,85 @@
 //!
 //! ## Macro Usage
 //!
-//! Every user of this module should create task graphs via the `empty_graph!`, `seq!`, `fork!`, and
-//! `task!` macros, which make it easy to construct task graphs correctly. Once a graph is ready,
-//! call `assemble` on it to mark the task entities for execution (by finalizing the root of the
-//! graph).
+//! Every user of this module should create task graphs via the `empty_graph!`, `seq!`, `fork!`,
+//! `race!`, `quorum!`, `retry!`, `timeout!`, `if_else!`, `repeat!`, `while_!`, and `task!` macros,
+//! which make it easy to construct task graphs correctly. Once a graph is ready, call `assemble` on
+//! it to mark the task entities for execution (by finalizing the root of the graph).
+//!
+//! A task fails by returning `TaskStatus::Failed` from `run`. The `OnFailure` passed to `assemble`
+//! decides what happens to the rest of the graph: `Abort` cancels everything that hasn't finished,
+//! `SkipDependents` only skips the tasks that depend on the failure (a fork then waits for its
+//! other prongs before failing), and `Continue` treats the failure like a completion. Use
+//! `entity_outcome` to find out how an entity finished. Outside of systems, use the `TaskHandle`
+//! returned by `assemble`, which still works after the graph is deleted. To start follow-up work as
+//! soon as a graph finishes, pass `GraphCallbacks` to `assemble_with_callbacks`.
+//!
+//! A task can produce a `TaskOutput` from `TaskComponent::take_output`, which is handed to the
+//! `TaskComponent::set_input` of the task that follows it in a `seq!`. `TaskOutput::downcast`
+//! turns an output of the wrong type into a `TaskError`; returning it from `set_input` fails the
+//! task before it runs, without calling its other hooks. Use `entity_output` to read the output of
+//! a whole graph. The first tasks of a sub-graph owned by a control node, like a `retry!`, don't
+//! get an input.
+//!
+//! Besides its `Data`, `run` gets a `TaskContext`, which knows the task's entity, the final entity
+//! of its graph, the tick of the `TaskClock`, both now and when the task first ran, and the time
+//! taken by the last tick. Tasks that need setup or teardown can
+//! implement `TaskComponent::on_unblocked` and `TaskComponent::on_finished`, which the runner calls
+//! before the first `run` and after the last one.
+//!
+//! Tasks of one type can run in parallel if they only need shared access to their `Data`. Implement
+//! `ParTaskComponent` for them and call `par_run_tasks` instead of `run_tasks` in their runner.
+//!
+//! While it runs, a task can tell how much of its work is done with `TaskContext::report_progress`.
+//! `graph_progress` and `weighted_graph_progress` add this up over a whole graph, for example to
+//! show a progress bar, and so does `TaskHandle::progress`.
+//!
+//! A task can also spawn a new graph while it runs, with `TaskContext::spawn_and_await`, and sleep
+//! until that graph finishes. This is useful when the amount of work is only known at runtime. A
+//! task that finishes without waiting for the graph it spawned fails, and the graph is cancelled.
+//!
+//! To add work to the end of a graph that is still running, call `append` with its final entity.
+//! The appended graph starts once the original one completes, and existing handles and callbacks
+//! move along to the end of the combined graph. If the original graph has already finished, the
+//! appended one runs as a new graph instead.
+//!
+//! The predicates of `if_else!` and `while_!` are evaluated on the `World`. To also read resources,
+//! write `resources:` before a predicate that takes the `Resources` as a second argument, and add
+//! `evaluate_task_predicates` to the schedule with `add_thread_local_fn`, after the task manager.
+//!
+//! A graph can be stopped early with `cancel`. Cancelled tasks are no longer run, and the ones that
+//! had already started get a call to `TaskComponent::on_cancel`.
 //!
 //! These systems must be scheduled for tasks to make progress:
 //!   - a system created with `build_task_manager_system`
 //!   - a system that calls `run_tasks` on each `TaskComponent` used
 //!
+//! Runners need the `TaskClock` resource, which measures ticks and time for `TaskContext`. It can be
+//! advanced by a system created with `build_task_clock_system`, scheduled before the others, or by
+//! calling `TaskClock::advance` from your own game loop. The task manager doesn't read it: a
+//! `Timeout` counts runs of the task manager system, or wall-clock time.
+//!
+//! Instead of polling graphs, other systems can read the `TaskEvents` resource to find out which
+//! entities were unblocked, finished, or deleted. Only the task manager created with
+//! `build_task_manager_system_with_events` needs that resource and adds to it. Its events pile up
+//! until a reader drains them with `TaskEvents::drain`.
+//!
+//! The built-in `WaitTicks` and `WaitDuration` tasks have ready-made runners, created with
+//! `build_wait_ticks_runner_system` and `build_wait_duration_runner_system`.
+//!
+//! Logic that is easier to write as an `async` block than as a state machine can run as a
+//! `FutureTask`. Each type of future needs its own runner, created with
+//! `build_future_task_runner_system`; box the future with `FutureTask::boxed` to give it a
+//! nameable type.
+//!
 //! ## Advanced Usage
 //!
 //! If you find the `TaskGraph` macros limiting, you can use the `make_task`, `join`, `make_fork`,
-//! and `add_prong` functions; these are the building blocks for creating all task graphs, including
-//! buggy ones. These functions are totally dynamic in that they deal directly with entities of
-//! various archetypes, assuming that the programmer passed in the correct archetypes for the given
-//! function.
+//! `make_race`, `make_quorum`, `add_prong`, `make_retry`, `make_timeout`, `make_branch`,
+//! `make_repeat`, and `make_while` functions; these are the building blocks for creating all task
+//! graphs, including buggy ones. These functions are totally dynamic in that they deal directly
+//! with entities of various archetypes, assuming that the programmer passed in the correct
+//! archetypes for the given function.
 //!
 //! Potential bugs that won't be detected for you:
 //!   - leaked orphan entities
@@ -200,24 +268,49 @@
 //!     components; these should only be used inside this module
 //!
 
+mod clock;
 mod components;
+mod context;
+mod events;
+mod future;
 mod graph_builder;
+mod handle;
 mod manager;
 mod runner;
+mod wait;
 
+pub use clock::{build_task_clock_system, TaskClock};
 pub use components::{
-    add_prong, finalize, join, make_fork, make_task, with_task_components, FinalTag, OnCompletion,
-    TaskComponent, TaskProgress,
+    add_prong, append, finalize, join, make_branch, make_fork, make_quorum, make_race, make_repeat,
+    make_retry, make_task, make_timeout, make_while, set_callbacks, with_task_components, FinalTag,
+    GraphCallback, GraphCallbacks, OnCompletion, OnFailure, OnQuorum, OnTimeout, ParTaskComponent,
+    Predicate, RunnableTag, TaskComponent, TaskError, TaskOutcome, TaskOutput, TaskProgress,
+    TaskStatus, Timeout,
 };
+pub use context::{ParTaskContext, TaskContext};
+pub use events::{TaskEvent, TaskEvents};
+pub use future::{build_future_task_runner_system, BoxedTaskFuture, FutureTask};
 pub use graph_builder::{Cons, TaskFactory, TaskGraph};
-pub use manager::{build_task_manager_system, entity_is_complete};
-pub use runner::{run_tasks, task_runner_query, TaskEntityFilter, TaskQuery, TaskSystemQuery};
+pub use handle::TaskHandle;
+pub use manager::{
+    build_task_manager_system, build_task_manager_system_with_events, cancel, entity_is_complete,
+    entity_outcome, entity_output, evaluate_task_predicates, graph_progress,
+    weighted_graph_progress,
+};
+pub use runner::{
+    par_run_tasks, run_tasks, task_runner_query, TaskEntityFilter, TaskQuery, TaskSystemQuery,
+};
+pub use wait::{
+    build_wait_duration_runner_system, build_wait_ticks_runner_system, WaitDuration, WaitTicks,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use legion::prelude::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[derive(Clone, Debug, Default, Eq, PartialEq)]
     struct Noop {
@@ -227,17 +320,20 @@ mod tests {
     impl<'a> TaskComponent<'a> for Noop {
         type Data = ();
 
-        fn run(&mut self, _data: &mut Self::Data) -> bool {
+        fn run(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
             self.was_run = true;
 
-            true
+            TaskStatus::Complete
         }
     }
 
     fn build_noop_task_runner_system() -> Box<dyn Schedulable> {
         SystemBuilder::new("noop_task_runner")
+            .read_resource::<TaskClock>()
             .with_query(task_runner_query::<Noop>())
-            .build(|_, mut world, _, task_query| run_tasks(&mut world, &mut (), task_query))
+            .build(|cmd, world, clock, task_query| {
+                run_tasks(cmd, world, clock, &mut (), task_query)
+            })
     }
 
     #[derive(Clone, Debug)]
@@ -248,34 +344,404 @@ mod tests {
     impl<'a> TaskComponent<'a> for PushValue {
         type Data = Vec<usize>;
 
-        fn run(&mut self, data: &mut Self::Data) -> bool {
+        fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
             log::debug!("Task pushing value {}", self.value);
             data.push(self.value);
 
-            true
+            TaskStatus::Complete
         }
     }
 
     fn build_push_value_task_runner_system() -> Box<dyn Schedulable> {
         SystemBuilder::new("example_task_runner")
+            .read_resource::<TaskClock>()
             .write_resource::<Vec<usize>>()
             .with_query(task_runner_query::<PushValue>())
-            .build(|_, mut world, value, task_query| {
-                run_tasks(&mut world, &mut **value, task_query)
+            .build(|cmd, world, (clock, value), task_query| {
+                run_tasks(cmd, world, clock, &mut **value, task_query)
+            })
+    }
+
+    /// Pushes one more than its input, or `value` if it has no input, and outputs what it pushed.
+    #[derive(Clone, Debug)]
+    struct AddOne {
+        value: usize,
+    }
+
+    impl<'a> TaskComponent<'a> for AddOne {
+        type Data = Vec<usize>;
+
+        fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+            self.value += 1;
+            data.push(self.value);
+
+            TaskStatus::Complete
+        }
+
+        fn set_input(&mut self, input: TaskOutput) -> Result<(), TaskError> {
+            self.value = *input.downcast::<usize>()?;
+            Ok(())
+        }
+
+        fn take_output(&mut self) -> Option<TaskOutput> {
+            Some(TaskOutput::new(self.value))
+        }
+    }
+
+    fn build_add_one_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("add_one_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<Vec<usize>>()
+            .with_query(task_runner_query::<AddOne>())
+            .build(|cmd, world, (clock, value), task_query| {
+                run_tasks(cmd, world, clock, &mut **value, task_query)
+            })
+    }
+
+    /// Spawns a sequence of `PushValue`s, or a `Fail` if `count` is zero, and then pushes `count`.
+    #[derive(Clone, Debug)]
+    struct Spawner {
+        count: usize,
+        has_spawned: bool,
+        // Whether the task waits for the graph it spawned, as it should.
+        waits: bool,
+    }
+
+    impl<'a> TaskComponent<'a> for Spawner {
+        type Data = Vec<usize>;
+
+        fn run(&mut self, data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+            if !self.has_spawned {
+                self.has_spawned = true;
+                let mut graph: TaskGraph = if self.count == 0 {
+                    task!(@Fail)
+                } else {
+                    empty_graph!()
+                };
+                for value in 0..self.count {
+                    graph = seq!(graph, @PushValue { value });
+                }
+                ctx.spawn_and_await(graph);
+
+                return if self.waits {
+                    TaskStatus::Running
+                } else {
+                    TaskStatus::Complete
+                };
+            }
+            data.push(self.count);
+
+            TaskStatus::Complete
+        }
+    }
+
+    fn build_spawner_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("spawner_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<Vec<usize>>()
+            .with_query(task_runner_query::<Spawner>())
+            .build(|cmd, world, (clock, value), task_query| {
+                run_tasks(cmd, world, clock, &mut **value, task_query)
+            })
+    }
+
+    #[derive(Clone, Debug)]
+    struct Fail;
+
+    impl<'a> TaskComponent<'a> for Fail {
+        type Data = ();
+
+        fn run(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+            TaskStatus::Failed(TaskError::new("failed on purpose"))
+        }
+    }
+
+    fn build_fail_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("fail_task_runner")
+            .read_resource::<TaskClock>()
+            .with_query(task_runner_query::<Fail>())
+            .build(|cmd, world, clock, task_query| {
+                run_tasks(cmd, world, clock, &mut (), task_query)
+            })
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Spin {
+        runs: usize,
+        was_cancelled: bool,
+    }
+
+    impl<'a> TaskComponent<'a> for Spin {
+        type Data = ();
+
+        fn run(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+            self.runs += 1;
+
+            TaskStatus::Running
+        }
+
+        fn on_cancel(&mut self) {
+            self.was_cancelled = true;
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Steps {
+        steps: usize,
+        done: usize,
+    }
+
+    impl Steps {
+        fn new(steps: usize) -> Self {
+            Steps { steps, done: 0 }
+        }
+    }
+
+    impl<'a> TaskComponent<'a> for Steps {
+        type Data = ();
+
+        fn run(&mut self, _data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+            self.done += 1;
+            ctx.report_progress(self.done as f32 / self.steps as f32);
+
+            if self.done == self.steps {
+                TaskStatus::Complete
+            } else {
+                TaskStatus::Running
+            }
+        }
+    }
+
+    fn build_steps_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("steps_task_runner")
+            .read_resource::<TaskClock>()
+            .with_query(task_runner_query::<Steps>())
+            .build(|cmd, world, clock, task_query| {
+                run_tasks(cmd, world, clock, &mut (), task_query)
+            })
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Inspect {
+        runs_left: usize,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Inspection {
+        entity: Entity,
+        root: Entity,
+        tick: u64,
+        unblocked_tick: u64,
+    }
+
+    impl<'a> TaskComponent<'a> for Inspect {
+        type Data = Vec<Inspection>;
+
+        fn run(&mut self, data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+            data.push(Inspection {
+                entity: ctx.entity(),
+                root: ctx.root(),
+                tick: ctx.tick(),
+                unblocked_tick: ctx.unblocked_tick(),
+            });
+            self.runs_left -= 1;
+
+            if self.runs_left == 0 {
+                TaskStatus::Complete
+            } else {
+                TaskStatus::Running
+            }
+        }
+    }
+
+    fn build_inspect_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("inspect_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<Vec<Inspection>>()
+            .with_query(task_runner_query::<Inspect>())
+            .build(|cmd, world, (clock, inspections), task_query| {
+                run_tasks(cmd, world, clock, &mut **inspections, task_query)
+            })
+    }
+
+    #[derive(Clone, Debug)]
+    struct Staged {
+        runs_left: usize,
+        fails: bool,
+    }
+
+    impl<'a> TaskComponent<'a> for Staged {
+        type Data = Vec<&'static str>;
+
+        fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+            data.push("run");
+            self.runs_left -= 1;
+
+            match (self.runs_left, self.fails) {
+                (0, false) => TaskStatus::Complete,
+                (0, true) => TaskStatus::Failed(TaskError::new("failed on purpose")),
+                _ => TaskStatus::Running,
+            }
+        }
+
+        fn on_unblocked(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) {
+            data.push("unblocked");
+        }
+
+        fn on_finished(
+            &mut self,
+            data: &mut Self::Data,
+            _ctx: &mut TaskContext,
+            outcome: &TaskOutcome,
+        ) {
+            data.push(match outcome {
+                TaskOutcome::Complete => "complete",
+                _ => "not complete",
+            });
+        }
+    }
+
+    fn build_staged_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("staged_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<Vec<&'static str>>()
+            .with_query(task_runner_query::<Staged>())
+            .build(|cmd, world, (clock, stages), task_query| {
+                run_tasks(cmd, world, clock, &mut **stages, task_query)
+            })
+    }
+
+    #[derive(Clone, Debug)]
+    struct SumValues {
+        runs_left: usize,
+        sum: usize,
+    }
+
+    impl SumValues {
+        fn new(runs: usize) -> Self {
+            SumValues {
+                runs_left: runs,
+                sum: 0,
+            }
+        }
+    }
+
+    impl<'a> TaskComponent<'a> for SumValues {
+        type Data = Vec<usize>;
+
+        fn run(&mut self, data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+            self.par_run(data, ctx.without_commands())
+        }
+
+        fn on_finished(
+            &mut self,
+            data: &mut Self::Data,
+            _ctx: &mut TaskContext,
+            _outcome: &TaskOutcome,
+        ) {
+            data.push(self.sum);
+        }
+
+        fn take_output(&mut self) -> Option<TaskOutput> {
+            Some(TaskOutput::new(self.sum))
+        }
+    }
+
+    impl<'a> ParTaskComponent<'a> for SumValues {
+        fn par_run(&mut self, data: &Self::Data, _ctx: &mut ParTaskContext) -> TaskStatus {
+            self.sum = data.iter().sum();
+            self.runs_left -= 1;
+
+            if self.runs_left == 0 {
+                TaskStatus::Complete
+            } else {
+                TaskStatus::Running
+            }
+        }
+    }
+
+    fn build_sum_values_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("sum_values_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<Vec<usize>>()
+            .with_query(task_runner_query::<SumValues>())
+            .build(|cmd, world, (clock, values), task_query| {
+                par_run_tasks(cmd, world, clock, &mut **values, task_query)
+            })
+    }
+
+    fn build_spin_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("spin_task_runner")
+            .read_resource::<TaskClock>()
+            .with_query(task_runner_query::<Spin>())
+            .build(|cmd, world, clock, task_query| {
+                run_tasks(cmd, world, clock, &mut (), task_query)
+            })
+    }
+
+    struct FailuresLeft(usize);
+
+    /// Fails until `FailuresLeft` reaches zero.
+    #[derive(Clone, Debug)]
+    struct Flaky;
+
+    impl<'a> TaskComponent<'a> for Flaky {
+        type Data = FailuresLeft;
+
+        fn run(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+            if data.0 == 0 {
+                return TaskStatus::Complete;
+            }
+            data.0 -= 1;
+
+            TaskStatus::Failed(TaskError::new("flaked"))
+        }
+    }
+
+    fn build_flaky_task_runner_system() -> Box<dyn Schedulable> {
+        SystemBuilder::new("flaky_task_runner")
+            .read_resource::<TaskClock>()
+            .write_resource::<FailuresLeft>()
+            .with_query(task_runner_query::<Flaky>())
+            .build(|cmd, world, (clock, failures_left), task_query| {
+                run_tasks(cmd, world, clock, &mut **failures_left, task_query)
             })
     }
 
-    fn set_up<'a, 'b>() -> (World, Resources, Schedule) {
+    fn set_up() -> (World, Resources, Schedule) {
         let mut resources = Resources::default();
         resources.insert::<Vec<usize>>(Vec::new());
+        resources.insert(FailuresLeft(0));
+        resources.insert(TaskClock::default());
+        resources.insert(TaskEvents::default());
+        resources.insert::<Vec<Inspection>>(Vec::new());
+        resources.insert::<Vec<&'static str>>(Vec::new());
 
         let world = World::new();
 
         let schedule = Schedule::builder()
             .add_system(build_noop_task_runner_system())
             .add_system(build_push_value_task_runner_system())
+            .add_system(build_add_one_task_runner_system())
+            .add_system(build_spawner_task_runner_system())
+            .add_system(build_fail_task_runner_system())
+            .add_system(build_spin_task_runner_system())
+            .add_system(build_steps_task_runner_system())
+            .add_system(build_inspect_task_runner_system())
+            .add_system(build_staged_task_runner_system())
+            .add_system(build_sum_values_task_runner_system())
+            .add_system(build_flaky_task_runner_system())
+            .add_system(build_wait_ticks_runner_system("wait_ticks_runner"))
+            .add_system(build_wait_duration_runner_system("wait_duration_runner"))
+            .add_system(build_future_task_runner_system::<
+                BoxedTaskFuture<usize>,
+                _,
+                _,
+            >("future_task_runner"))
             // For sake of reproducible tests, assume the manager system is the last to run.
-            .add_system(build_task_manager_system("task_manager"))
+            .add_system(build_task_manager_system_with_events("task_manager"))
+            .flush()
+            .add_thread_local_fn(evaluate_task_predicates)
             .build();
 
         (world, resources, schedule)
@@ -284,14 +750,26 @@ mod tests {
     fn assemble_task_graph(
         make_task_graph: fn() -> TaskGraph,
         on_completion: OnCompletion,
+        on_failure: OnFailure,
         world: &mut World,
         resources: &mut Resources,
     ) -> Entity {
-        resources.insert::<Option<Entity>>(None);
+        assemble_task_graph_handle(make_task_graph, on_completion, on_failure, world, resources)
+            .entity()
+    }
+
+    fn assemble_task_graph_handle(
+        make_task_graph: fn() -> TaskGraph,
+        on_completion: OnCompletion,
+        on_failure: OnFailure,
+        world: &mut World,
+        resources: &mut Resources,
+    ) -> TaskHandle {
+        resources.insert::<Option<TaskHandle>>(None);
         let assemble_system = SystemBuilder::new("assembler")
-            .write_resource::<Option<Entity>>()
-            .build(move |mut cmd, _subworld, final_task, _| {
-                **final_task = Some(make_task_graph().assemble(on_completion, &mut cmd));
+            .write_resource::<Option<TaskHandle>>()
+            .build(move |cmd, _subworld, handle, _| {
+                **handle = Some(make_task_graph().assemble(on_completion, on_failure, cmd));
             });
         let mut assemble_schedule = Schedule::builder()
             .add_system(assemble_system)
@@ -299,7 +777,22 @@ mod tests {
             .build();
         assemble_schedule.execute(world, resources);
 
-        resources.get::<Option<Entity>>().unwrap().unwrap()
+        resources
+            .get::<Option<TaskHandle>>()
+            .unwrap()
+            .clone()
+            .unwrap()
+    }
+
+    fn cancel_task_graph(root: Entity, world: &mut World, resources: &mut Resources) {
+        let cancel_system = SystemBuilder::new("canceller").build(move |cmd, _, _, _| {
+            cancel(cmd, root);
+        });
+        let mut cancel_schedule = Schedule::builder()
+            .add_system(cancel_system)
+            .flush()
+            .build();
+        cancel_schedule.execute(world, resources);
     }
 
     fn assert_task_is_complete(
@@ -311,7 +804,7 @@ mod tests {
         let assert_system =
             with_task_components(SystemBuilder::new("asserter")).build(move |_, subworld, _, _| {
                 if is_alive {
-                    assert!(entity_is_complete(&subworld, task));
+                    assert!(entity_is_complete(subworld, task));
                 }
                 assert_eq!(subworld.is_alive(task), is_alive);
             });
@@ -319,6 +812,20 @@ mod tests {
         assert_schedule.execute(world, resources);
     }
 
+    fn assert_task_outcome(
+        task: Entity,
+        expected: Option<TaskOutcome>,
+        world: &mut World,
+        resources: &mut Resources,
+    ) {
+        let assert_system =
+            with_task_components(SystemBuilder::new("asserter")).build(move |_, subworld, _, _| {
+                assert_eq!(entity_outcome(subworld, task), expected);
+            });
+        let mut assert_schedule = Schedule::builder().add_system(assert_system).build();
+        assert_schedule.execute(world, resources);
+    }
+
     #[test]
     fn run_single_task() {
         let (mut world, mut resources, mut schedule) = set_up();
@@ -329,6 +836,7 @@ mod tests {
         let root = assemble_task_graph(
             make_task_graph,
             OnCompletion::None,
+            OnFailure::Abort,
             &mut world,
             &mut resources,
         );
@@ -353,6 +861,7 @@ mod tests {
         let root = assemble_task_graph(
             make_task_graph,
             OnCompletion::Delete,
+            OnFailure::Abort,
             &mut world,
             &mut resources,
         );
@@ -377,6 +886,7 @@ mod tests {
         let root = assemble_task_graph(
             make_task_graph,
             OnCompletion::Delete,
+            OnFailure::Abort,
             &mut world,
             &mut resources,
         );
@@ -408,6 +918,7 @@ mod tests {
         let root = assemble_task_graph(
             make_task_graph,
             OnCompletion::Delete,
+            OnFailure::Abort,
             &mut world,
             &mut resources,
         );
@@ -440,6 +951,7 @@ mod tests {
         let root = assemble_task_graph(
             make_task_graph,
             OnCompletion::Delete,
+            OnFailure::Abort,
             &mut world,
             &mut resources,
         );
@@ -461,4 +973,2219 @@ mod tests {
 
         assert_task_is_complete(root, false, &mut world, &mut resources);
     }
+
+    #[test]
+    fn failed_task_reports_its_error() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@Fail)
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn dependents_of_failed_task_are_not_run() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Fail, @PushValue { value: 1 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    fn make_fork_with_failing_prong() -> TaskGraph {
+        seq!(
+            fork!(@Fail, seq!(@PushValue { value: 1 }, @PushValue { value: 2 })),
+            @PushValue { value: 3 }
+        )
+    }
+
+    #[test]
+    fn abort_cancels_other_prongs() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        let root = assemble_task_graph(
+            make_fork_with_failing_prong,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn skip_dependents_waits_for_other_prongs() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        let root = assemble_task_graph(
+            make_fork_with_failing_prong,
+            OnCompletion::None,
+            OnFailure::SkipDependents,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn continue_ignores_failure() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        let root = assemble_task_graph(
+            make_fork_with_failing_prong,
+            OnCompletion::None,
+            OnFailure::Continue,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn cancelled_task_stops_running_and_calls_hook() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@Spin::default())
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        cancel_task_graph(root, &mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        {
+            let spin = world.get_component::<Spin>(root).unwrap();
+            assert_eq!(spin.runs, 1);
+            assert!(spin.was_cancelled);
+        }
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Cancelled),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn cancelled_graph_deleted_on_completion() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Spin::default(), @PushValue { value: 1 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        cancel_task_graph(root, &mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn very_long_sequence_reports_progress_and_is_cancelled_and_deleted() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            let mut s: TaskGraph = empty_graph!();
+            for value in 0..100_000 {
+                s = seq!(s, @PushValue { value });
+            }
+            s
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(handle.progress(&world), 2.0 / 100_000.0);
+        assert_eq!(graph_progress(&world, handle.entity()), 2.0 / 100_000.0);
+        cancel_task_graph(handle.entity(), &mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![0, 1]);
+        assert_eq!(handle.outcome(&world), Some(TaskOutcome::Cancelled));
+        assert!(!world.is_alive(handle.entity()));
+    }
+
+    #[test]
+    fn retry_of_very_long_sequence_is_deleted() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            let mut s: TaskGraph = empty_graph!();
+            for value in 0..100_000 {
+                s = seq!(s, @PushValue { value });
+            }
+            retry!(2, s)
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        // Deleting the retry entity drops the last copy of the sequence.
+        cancel_task_graph(handle.entity(), &mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(handle.outcome(&world), Some(TaskOutcome::Cancelled));
+        assert!(!world.is_alive(handle.entity()));
+    }
+
+    #[test]
+    fn deeply_nested_forks_run_to_completion() {
+        const DEPTH: usize = 10_000;
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            let mut g: TaskGraph = empty_graph!();
+            for i in 0..DEPTH {
+                g = seq!(
+                    fork!(g, @PushValue { value: i }),
+                    @PushValue { value: DEPTH + i }
+                );
+            }
+            g
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        let mut frames = 0;
+        while handle.outcome(&world).is_none() && frames < 3 * DEPTH {
+            schedule.execute(&mut world, &mut resources);
+            frames += 1;
+        }
+
+        assert_eq!(handle.outcome(&world), Some(TaskOutcome::Complete));
+        let values = resources.get::<Vec<usize>>().unwrap();
+        assert_eq!(values.len(), 2 * DEPTH);
+        // Each fork's sequential side only runs once the fork it wraps has finished.
+        let after_forks: Vec<usize> = values.iter().copied().filter(|v| *v >= DEPTH).collect();
+        assert_eq!(after_forks, (DEPTH..2 * DEPTH).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retry_runs_fresh_copies_until_success() {
+        let (mut world, mut resources, mut schedule) = set_up();
+        resources.insert(FailuresLeft(2));
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                retry!(3, seq!(@PushValue { value: 1 }, @Flaky)),
+                @PushValue { value: 2 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..20 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 1, 1, 2]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn retry_fails_after_last_attempt() {
+        let (mut world, mut resources, mut schedule) = set_up();
+        resources.insert(FailuresLeft(5));
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(retry!(2, @Flaky), @PushValue { value: 1 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..20 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(resources.get::<FailuresLeft>().unwrap().0, 3);
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn timeout_fails_task_that_never_finishes() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                timeout!(Timeout::Ticks(3), OnTimeout::Fail, @Spin::default()),
+                @PushValue { value: 1 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("timed out"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn timeout_cancels_graph() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            timeout!(
+                Timeout::Duration(std::time::Duration::from_secs(0)),
+                OnTimeout::Cancel,
+                seq!(@Spin::default(), @PushValue { value: 1 })
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn task_finishing_before_timeout_completes() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        // Unblocked on the first run of the task manager, the timeouts expire on its fourth run.
+        // Tasks run before the manager in each frame, so by then the first task has run three
+        // times and completed, while the second one needs one more run.
+        fn make_task_graph() -> TaskGraph {
+            timeout!(Timeout::Ticks(3), OnTimeout::Fail, @Steps::new(3))
+        }
+        fn make_slower_task_graph() -> TaskGraph {
+            timeout!(Timeout::Ticks(3), OnTimeout::Fail, @Steps::new(4))
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        let slower_root = assemble_task_graph(
+            make_slower_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+        assert_task_outcome(
+            slower_root,
+            Some(TaskOutcome::Failed(TaskError::new("timed out"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn race_completes_with_first_prong_and_cancels_the_rest() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                race!(@Spin::default(), seq!(@PushValue { value: 1 }, @PushValue { value: 2 })),
+                @PushValue { value: 3 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3]);
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn race_and_quorum_wait_for_prong_next_to_empty_one() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                race!(seq!(@PushValue { value: 1 }, @PushValue { value: 2 }), Cons::Nil),
+                quorum!(
+                    1,
+                    OnQuorum::CancelRest,
+                    seq!(@PushValue { value: 3 }, @PushValue { value: 4 }),
+                    Cons::Nil
+                ),
+                @PushValue { value: 5 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..10 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3, 4, 5]);
+        assert_task_is_complete(root, true, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn race_only_fails_when_no_prong_can_complete() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            race!(@Fail, seq!(@PushValue { value: 1 }, @PushValue { value: 2 }))
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn quorum_cancels_rest_once_enough_prongs_complete() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                quorum!(
+                    2,
+                    OnQuorum::CancelRest,
+                    @PushValue { value: 1 },
+                    @Spin::default(),
+                    @PushValue { value: 2 }
+                ),
+                @PushValue { value: 3 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+        let query = <Read<Spin>>::query();
+        let spin = query.iter(&world).next().unwrap();
+        assert!(spin.was_cancelled);
+    }
+
+    #[test]
+    fn quorum_leaves_rest_running() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                quorum!(
+                    1,
+                    OnQuorum::LeaveRunning,
+                    @PushValue { value: 1 },
+                    seq!(@Spin::default(), @PushValue { value: 2 })
+                ),
+                @PushValue { value: 3 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 3]);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+        let query = <Read<Spin>>::query();
+        let spin = query.iter(&world).next().unwrap();
+        assert!(spin.runs > 1);
+        assert!(!spin.was_cancelled);
+    }
+
+    #[test]
+    fn prongs_left_running_are_maintained_after_graph_finishes() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            quorum!(
+                1,
+                OnQuorum::LeaveRunning,
+                @PushValue { value: 1 },
+                seq!(@Steps::new(3), @PushValue { value: 2 })
+            )
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        let events = execute_and_collect_events(8, &mut schedule, &mut world, &mut resources);
+
+        assert!(handle.is_complete(&world));
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2]);
+        let last = <Read<PushValue>>::query()
+            .iter_entities(&world)
+            .find(|(_, task)| task.value == 2)
+            .unwrap()
+            .0;
+        assert!(events.contains(&TaskEvent::Finished(last, TaskOutcome::Complete)));
+    }
+
+    #[test]
+    fn quorum_fails_once_too_few_prongs_can_complete() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            quorum!(
+                2,
+                OnQuorum::CancelRest,
+                @Fail,
+                @Spin::default(),
+                @Fail
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    struct Mode {
+        take_then: bool,
+    }
+
+    fn mode_takes_then(world: &World) -> bool {
+        <Read<Mode>>::query()
+            .iter(world)
+            .next()
+            .is_some_and(|mode| mode.take_then)
+    }
+
+    fn run_branch(take_then: bool) -> (Vec<usize>, usize, World, Resources, Entity) {
+        let (mut world, mut resources, mut schedule) = set_up();
+        world.insert((), vec![(Mode { take_then },)]);
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @PushValue { value: 1 },
+                if_else!(mode_takes_then, @Spin::default(), @PushValue { value: 2 }),
+                @PushValue { value: 3 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        let values = resources.get::<Vec<usize>>().unwrap().clone();
+        let num_spins = <Read<Spin>>::query().iter(&world).count();
+
+        (values, num_spins, world, resources, root)
+    }
+
+    #[test]
+    fn branch_only_assembles_else_side() {
+        let (values, num_spins, mut world, mut resources, root) = run_branch(false);
+
+        assert_eq!(values, vec![1, 2, 3]);
+        assert_eq!(num_spins, 0);
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn branch_only_assembles_then_side() {
+        let (values, num_spins, mut world, mut resources, root) = run_branch(true);
+
+        assert_eq!(values, vec![1]);
+        assert_eq!(num_spins, 1);
+        assert_task_outcome(root, None, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn repeat_runs_fresh_copies_in_a_row() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                repeat!(3, seq!(@PushValue { value: 1 }, @PushValue { value: 2 })),
+                @PushValue { value: 3 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..20 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(
+            *resources.get::<Vec<usize>>().unwrap(),
+            vec![1, 2, 1, 2, 1, 2, 3]
+        );
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Complete),
+            &mut world,
+            &mut resources,
+        );
+        // Finished iterations are deleted.
+        assert_eq!(<Read<PushValue>>::query().iter(&world).count(), 1);
+    }
+
+    #[test]
+    fn while_runs_until_predicate_is_false() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        static LAPS_LEFT: AtomicUsize = AtomicUsize::new(2);
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                while_!(
+                    |_| LAPS_LEFT
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                        .is_ok(),
+                    @PushValue { value: 1 }
+                ),
+                @PushValue { value: 2 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..20 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 1, 2]);
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn predicates_can_read_resources() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn num_values(resources: &Resources) -> usize {
+            resources.get::<Vec<usize>>().unwrap().len()
+        }
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                while_!(resources: |_, r| num_values(r) < 3, @PushValue { value: 1 }),
+                if_else!(
+                    resources: |_, r| num_values(r) == 3,
+                    @PushValue { value: 2 },
+                    @PushValue { value: 3 }
+                )
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..20 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 1, 1, 2]);
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+    }
+
+    fn advance_clock(delta: std::time::Duration, resources: &mut Resources) {
+        resources.get_mut::<TaskClock>().unwrap().advance(delta);
+    }
+
+    #[test]
+    fn wait_ticks_waits_for_clock_to_tick() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@WaitTicks::new(2), @PushValue { value: 1 })
+        }
+        assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        // The clock is stopped.
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+
+        advance_clock(std::time::Duration::default(), &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+
+        advance_clock(std::time::Duration::default(), &mut resources);
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn wait_duration_waits_for_clock_time() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @WaitDuration::new(std::time::Duration::from_secs(3)),
+                @PushValue { value: 1 }
+            )
+        }
+        assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+        for _ in 0..2 {
+            advance_clock(std::time::Duration::from_secs(1), &mut resources);
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+
+        advance_clock(std::time::Duration::from_secs(1), &mut resources);
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1]);
+    }
+
+    fn output_of(entity: Entity, world: &World) -> Option<usize> {
+        world
+            .get_component::<TaskProgress>(entity)
+            .unwrap()
+            .output()
+            .map(|output| *output.get::<usize>().unwrap())
+    }
+
+    #[test]
+    fn outputs_are_passed_along_a_sequence() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @AddOne { value: 0 },
+                @AddOne { value: 0 },
+                @AddOne { value: 0 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3]);
+        assert_eq!(output_of(root, &world), Some(3));
+    }
+
+    #[test]
+    fn input_of_the_wrong_type_fails_the_task() {
+        #[derive(Clone, Debug)]
+        struct Name;
+
+        impl<'a> TaskComponent<'a> for Name {
+            type Data = ();
+
+            fn run(&mut self, _data: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+                TaskStatus::Complete
+            }
+
+            fn take_output(&mut self) -> Option<TaskOutput> {
+                Some(TaskOutput::new("name"))
+            }
+        }
+
+        let (mut world, mut resources, _) = set_up();
+        let mut schedule = Schedule::builder()
+            .add_system(
+                SystemBuilder::new("name_task_runner")
+                    .read_resource::<TaskClock>()
+                    .with_query(task_runner_query::<Name>())
+                    .build(|cmd, world, clock, task_query| {
+                        run_tasks(cmd, world, clock, &mut (), task_query)
+                    }),
+            )
+            .add_system(build_add_one_task_runner_system())
+            .add_system(build_task_manager_system("task_manager"))
+            .build();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Name, @AddOne { value: 0 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_eq!(
+            handle.outcome(&world),
+            Some(TaskOutcome::Failed(TaskError::new(
+                "expected an output of type usize, got &str"
+            )))
+        );
+    }
+
+    #[test]
+    fn task_rejecting_its_input_gets_no_hooks() {
+        /// Expects a name as its input, and records its hooks.
+        #[derive(Clone, Debug)]
+        struct Greet;
+
+        impl<'a> TaskComponent<'a> for Greet {
+            type Data = Vec<&'static str>;
+
+            fn run(&mut self, data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+                self.par_run(data, ctx.without_commands())
+            }
+
+            fn on_unblocked(&mut self, data: &mut Self::Data, _ctx: &mut TaskContext) {
+                data.push("unblocked");
+            }
+
+            fn on_finished(
+                &mut self,
+                data: &mut Self::Data,
+                _ctx: &mut TaskContext,
+                _outcome: &TaskOutcome,
+            ) {
+                data.push("finished");
+            }
+
+            fn set_input(&mut self, input: TaskOutput) -> Result<(), TaskError> {
+                input.downcast::<&'static str>().map(|_| ())
+            }
+        }
+
+        impl<'a> ParTaskComponent<'a> for Greet {
+            fn par_run(&mut self, _data: &Self::Data, _ctx: &mut ParTaskContext) -> TaskStatus {
+                TaskStatus::Complete
+            }
+        }
+
+        for par in [false, true] {
+            let (mut world, mut resources, _) = set_up();
+            let mut schedule = Schedule::builder()
+                .add_system(
+                    SystemBuilder::new("greet_task_runner")
+                        .read_resource::<TaskClock>()
+                        .write_resource::<Vec<&'static str>>()
+                        .with_query(task_runner_query::<Greet>())
+                        .build(move |cmd, world, (clock, stages), task_query| {
+                            if par {
+                                par_run_tasks(cmd, world, clock, &mut **stages, task_query)
+                            } else {
+                                run_tasks(cmd, world, clock, &mut **stages, task_query)
+                            }
+                        }),
+                )
+                .add_system(build_add_one_task_runner_system())
+                .add_system(build_task_manager_system("task_manager"))
+                .build();
+
+            fn make_task_graph() -> TaskGraph {
+                seq!(@AddOne { value: 0 }, @Greet)
+            }
+            let handle = assemble_task_graph_handle(
+                make_task_graph,
+                OnCompletion::None,
+                OnFailure::Abort,
+                &mut world,
+                &mut resources,
+            );
+
+            for _ in 0..3 {
+                schedule.execute(&mut world, &mut resources);
+            }
+
+            assert!(resources.get::<Vec<&'static str>>().unwrap().is_empty());
+            assert_eq!(
+                handle.outcome(&world),
+                Some(TaskOutcome::Failed(TaskError::new(
+                    "expected an output of type &str, got usize"
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn race_passes_on_output_of_winner() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                race!(@Spin::default(), @AddOne { value: 10 }),
+                @AddOne { value: 0 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![11, 12]);
+        assert_eq!(output_of(root, &world), Some(12));
+    }
+
+    #[test]
+    fn handle_remembers_outcome_after_graph_is_deleted() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@AddOne { value: 0 }, @AddOne { value: 0 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        assert_eq!(handle.outcome(&world), None);
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(!world.is_alive(handle.entity()));
+        assert!(handle.is_complete(&world));
+        let output = handle.output(&world).unwrap();
+        assert_eq!(output.get::<usize>(), Some(&2));
+    }
+
+    #[test]
+    fn handle_reports_failure() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Fail, @PushValue { value: 1 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(handle.is_finished(&world));
+        assert!(!handle.is_complete(&world));
+        assert_eq!(
+            handle.outcome(&world),
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose")))
+        );
+        assert!(handle.output(&world).is_none());
+    }
+
+    fn execute_and_collect_events(
+        runs: usize,
+        schedule: &mut Schedule,
+        world: &mut World,
+        resources: &mut Resources,
+    ) -> Vec<TaskEvent> {
+        let mut events = Vec::new();
+        for _ in 0..runs {
+            schedule.execute(world, resources);
+            events.extend(resources.get_mut::<TaskEvents>().unwrap().drain());
+        }
+
+        events
+    }
+
+    #[test]
+    fn events_pile_up_until_drained() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@AddOne { value: 0 }, @AddOne { value: 0 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        let events: Vec<TaskEvent> = resources.get_mut::<TaskEvents>().unwrap().drain().collect();
+        assert!(events.contains(&TaskEvent::GraphFinished(root, TaskOutcome::Complete)));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(e, TaskEvent::Unblocked(_)))
+                .count(),
+            2
+        );
+        assert!(resources.get::<TaskEvents>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn task_manager_without_events_needs_no_task_events_resource() {
+        let mut resources = Resources::default();
+        resources.insert::<Vec<usize>>(Vec::new());
+        resources.insert(TaskClock::default());
+        let mut world = World::new();
+        let mut schedule = Schedule::builder()
+            .add_system(build_add_one_task_runner_system())
+            .add_system(build_task_manager_system("task_manager"))
+            .build();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@AddOne { value: 0 }, @AddOne { value: 0 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2]);
+        assert!(handle.is_complete(&world));
+    }
+
+    #[test]
+    fn task_manager_times_out_without_task_clock_resource() {
+        let mut resources = Resources::default();
+        let mut world = World::new();
+        let mut schedule = Schedule::builder()
+            .add_system(build_task_manager_system("task_manager"))
+            .build();
+
+        // Nothing runs the task, so only the timeout can finish the graph.
+        fn make_task_graph() -> TaskGraph {
+            timeout!(Timeout::Ticks(2), OnTimeout::Cancel, @Spin::default())
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(handle.outcome(&world), Some(TaskOutcome::Cancelled));
+    }
+
+    #[test]
+    fn events_follow_a_graph_from_start_to_deletion() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@PushValue { value: 1 }, @PushValue { value: 2 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        let first = world
+            .get_component::<components::SingleEdge>(root)
+            .unwrap()
+            .child;
+
+        let events = execute_and_collect_events(4, &mut schedule, &mut world, &mut resources);
+
+        assert_eq!(
+            events,
+            vec![
+                TaskEvent::Unblocked(first),
+                TaskEvent::Finished(first, TaskOutcome::Complete),
+                TaskEvent::Unblocked(root),
+                TaskEvent::Finished(root, TaskOutcome::Complete),
+                TaskEvent::GraphFinished(root, TaskOutcome::Complete),
+                TaskEvent::Deleted(first),
+                TaskEvent::Deleted(root),
+            ]
+        );
+    }
+
+    #[test]
+    fn events_report_every_cancelled_entity() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Spin::default(), @PushValue { value: 1 })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        let spin = world
+            .get_component::<components::SingleEdge>(root)
+            .unwrap()
+            .child;
+
+        execute_and_collect_events(1, &mut schedule, &mut world, &mut resources);
+        cancel_task_graph(root, &mut world, &mut resources);
+        let events = execute_and_collect_events(2, &mut schedule, &mut world, &mut resources);
+
+        assert_eq!(
+            events,
+            vec![
+                TaskEvent::Finished(root, TaskOutcome::Cancelled),
+                TaskEvent::Finished(spin, TaskOutcome::Cancelled),
+                TaskEvent::GraphFinished(root, TaskOutcome::Cancelled),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_unblocked_tasks_are_runnable() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Steps::new(2), @Spin::default())
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        let steps = world
+            .get_component::<components::SingleEdge>(root)
+            .unwrap()
+            .child;
+        let is_runnable = |world: &World, entity| world.has_component::<RunnableTag>(entity);
+        assert!(!is_runnable(&world, steps));
+
+        schedule.execute(&mut world, &mut resources);
+        assert!(is_runnable(&world, steps));
+        assert!(!is_runnable(&world, root));
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(!is_runnable(&world, steps));
+        assert!(is_runnable(&world, root));
+
+        cancel_task_graph(root, &mut world, &mut resources);
+        assert!(!is_runnable(&world, root));
+    }
+
+    #[test]
+    fn task_cancelled_as_it_is_unblocked_is_not_runnable() {
+        let (mut world, mut resources, _) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@Spin::default())
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        // The cancel command is flushed before the command that unblocks the task.
+        let cancel_system = SystemBuilder::new("canceller").build(move |cmd, _, _, _| {
+            cancel(cmd, root);
+        });
+        let mut schedule = Schedule::builder()
+            .add_system(cancel_system)
+            .add_system(build_task_manager_system("task_manager"))
+            .flush()
+            .build();
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(
+            world
+                .get_component::<TaskProgress>(root)
+                .and_then(|progress| progress.outcome()),
+            Some(TaskOutcome::Cancelled)
+        );
+        assert!(!world.has_component::<RunnableTag>(root));
+    }
+
+    struct Notified {
+        entity: Entity,
+        was_alive: bool,
+    }
+
+    fn notify(cmd: &mut CommandBuffer, entity: Entity) {
+        cmd.exec_mut(move |world| {
+            let was_alive = world.is_alive(entity);
+            world.insert((), vec![(Notified { entity, was_alive },)]);
+        });
+    }
+
+    fn assemble_task_graph_with_callbacks(
+        make_task_graph: fn() -> TaskGraph,
+        on_completion: OnCompletion,
+        make_callbacks: fn() -> GraphCallbacks,
+        world: &mut World,
+        resources: &mut Resources,
+    ) -> Entity {
+        resources.insert::<Option<Entity>>(None);
+        let assemble_system = SystemBuilder::new("assembler")
+            .write_resource::<Option<Entity>>()
+            .build(move |cmd, _subworld, final_task, _| {
+                let handle = make_task_graph().assemble_with_callbacks(
+                    on_completion,
+                    OnFailure::Abort,
+                    make_callbacks(),
+                    cmd,
+                );
+                **final_task = Some(handle.entity());
+            });
+        let mut assemble_schedule = Schedule::builder()
+            .add_system(assemble_system)
+            .flush()
+            .build();
+        assemble_schedule.execute(world, resources);
+
+        resources.get::<Option<Entity>>().unwrap().unwrap()
+    }
+
+    #[test]
+    fn on_complete_runs_before_graph_is_deleted() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@PushValue { value: 1 }, @PushValue { value: 2 })
+        }
+        fn make_callbacks() -> GraphCallbacks {
+            GraphCallbacks::default()
+                .on_complete(notify)
+                .on_fail(|_, _| panic!("Graph shouldn't fail"))
+        }
+        let root = assemble_task_graph_with_callbacks(
+            make_task_graph,
+            OnCompletion::Delete,
+            make_callbacks,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        let query = <Read<Notified>>::query();
+        let notified: Vec<_> = query
+            .iter(&world)
+            .map(|n| (n.entity, n.was_alive))
+            .collect();
+        assert_eq!(notified, vec![(root, true)]);
+        assert!(!world.is_alive(root));
+    }
+
+    #[test]
+    fn on_fail_runs_when_graph_fails() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Fail, @PushValue { value: 1 })
+        }
+        fn make_callbacks() -> GraphCallbacks {
+            GraphCallbacks::default()
+                .on_complete(|_, _| panic!("Graph shouldn't complete"))
+                .on_fail(notify)
+        }
+        let root = assemble_task_graph_with_callbacks(
+            make_task_graph,
+            OnCompletion::None,
+            make_callbacks,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        let query = <Read<Notified>>::query();
+        assert_eq!(query.iter(&world).count(), 1);
+        assert_eq!(query.iter(&world).next().unwrap().entity, root);
+    }
+
+    #[test]
+    fn task_awaits_graph_it_spawned() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @PushValue { value: 7 },
+                @Spawner {
+                    count: 3,
+                    has_spawned: false,
+                    waits: true
+                },
+                @PushValue { value: 8 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..12 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(
+            *resources.get::<Vec<usize>>().unwrap(),
+            vec![7, 0, 1, 2, 3, 8]
+        );
+        assert_task_is_complete(root, false, &mut world, &mut resources);
+        // The spawned graph was deleted along with the rest.
+        assert_eq!(<Read<PushValue>>::query().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn task_fails_with_graph_it_spawned() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @Spawner {
+                    count: 0,
+                    has_spawned: false,
+                    waits: true
+                },
+                @PushValue { value: 8 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..8 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn task_completing_without_awaiting_graph_it_spawned_fails() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@Spawner {
+                count: 2,
+                has_spawned: false,
+                waits: false
+            })
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new(
+                "completed while awaiting a graph it spawned",
+            ))),
+            &mut world,
+            &mut resources,
+        );
+        // The spawned graph is cancelled rather than left blocked.
+        let spawned: Vec<Option<TaskOutcome>> = <(Read<PushValue>, Read<TaskProgress>)>::query()
+            .iter(&world)
+            .map(|(_, progress)| progress.outcome())
+            .collect();
+        assert_eq!(spawned, vec![Some(TaskOutcome::Cancelled); 2]);
+    }
+
+    fn append_task_graph(
+        existing_final: Entity,
+        make_task_graph: fn() -> TaskGraph,
+        world: &mut World,
+        resources: &mut Resources,
+    ) -> Entity {
+        resources.insert::<Option<Entity>>(None);
+        let append_system = SystemBuilder::new("appender")
+            .write_resource::<Option<Entity>>()
+            .build(move |cmd, _subworld, final_task, _| {
+                **final_task = Some(append(cmd, existing_final, make_task_graph()));
+            });
+        let mut append_schedule = Schedule::builder()
+            .add_system(append_system)
+            .flush()
+            .build();
+        append_schedule.execute(world, resources);
+
+        resources.get::<Option<Entity>>().unwrap().unwrap()
+    }
+
+    #[test]
+    fn appended_graph_runs_after_existing_graph() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@PushValue { value: 1 }, @PushValue { value: 2 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+
+        fn make_appended_graph() -> TaskGraph {
+            seq!(@AddOne { value: 2 }, @PushValue { value: 4 })
+        }
+        let old_final = handle.entity();
+        let new_final =
+            append_task_graph(old_final, make_appended_graph, &mut world, &mut resources);
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3, 4]);
+        assert!(handle.is_complete(&world));
+        assert!(!world.is_alive(old_final));
+        assert!(!world.is_alive(new_final));
+        assert_eq!(<Read<PushValue>>::query().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn graph_appended_to_finished_graph_runs_on_its_own() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@PushValue { value: 1 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(handle.is_complete(&world));
+        assert!(!world.is_alive(handle.entity()));
+
+        fn make_appended_graph() -> TaskGraph {
+            task!(@PushValue { value: 2 })
+        }
+        let new_final = append_task_graph(
+            handle.entity(),
+            make_appended_graph,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2]);
+        assert_task_is_complete(new_final, true, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn handle_follows_appended_graph() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@PushValue { value: 1 })
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        fn make_appended_graph() -> TaskGraph {
+            task!(@Spin::default())
+        }
+        let old_final = handle.entity();
+        let new_final =
+            append_task_graph(old_final, make_appended_graph, &mut world, &mut resources);
+
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(handle.entity(), new_final);
+        assert_task_is_complete(old_final, true, &mut world, &mut resources);
+        assert!(!handle.is_finished(&world));
+    }
+
+    #[test]
+    fn append_moves_callbacks_to_new_final_entity() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@PushValue { value: 1 }, @PushValue { value: 2 })
+        }
+        fn make_callbacks() -> GraphCallbacks {
+            GraphCallbacks::default().on_complete(notify)
+        }
+        let root = assemble_task_graph_with_callbacks(
+            make_task_graph,
+            OnCompletion::None,
+            make_callbacks,
+            &mut world,
+            &mut resources,
+        );
+        fn make_appended_graph() -> TaskGraph {
+            seq!(@PushValue { value: 3 })
+        }
+        let new_final = append_task_graph(root, make_appended_graph, &mut world, &mut resources);
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 3]);
+        let query = <Read<Notified>>::query();
+        let notified: Vec<_> = query.iter(&world).map(|n| n.entity).collect();
+        assert_eq!(notified, vec![new_final]);
+        assert!(world.get_component::<FinalTag>(root).is_none());
+    }
+
+    // Returns `Pending` once, waking itself right away.
+    #[derive(Default)]
+    struct YieldNow {
+        has_yielded: bool,
+    }
+
+    impl std::future::Future for YieldNow {
+        type Output = ();
+
+        fn poll(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context,
+        ) -> std::task::Poll<()> {
+            if self.has_yielded {
+                std::task::Poll::Ready(())
+            } else {
+                self.has_yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn future_task_completes_with_output() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @FutureTask::boxed(|| async {
+                    YieldNow::default().await;
+                    YieldNow::default().await;
+                    Ok(5usize)
+                }),
+                @AddOne { value: 0 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+
+        for _ in 0..3 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![6]);
+        assert_task_is_complete(root, true, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn future_task_is_only_polled_when_woken() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn make_task_graph() -> TaskGraph {
+            task!(@FutureTask::boxed(|| std::future::poll_fn(|_| {
+                POLLS.fetch_add(1, Ordering::SeqCst);
+                std::task::Poll::<Result<usize, TaskError>>::Pending
+            })))
+        }
+        let (mut world, mut resources, mut schedule) = set_up();
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+        assert_task_outcome(root, None, &mut world, &mut resources);
+    }
+
+    #[test]
+    fn future_task_fails_with_error() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @FutureTask::boxed(|| async {
+                    YieldNow::default().await;
+                    Err::<usize, _>(TaskError::new("failed on purpose"))
+                }),
+                @PushValue { value: 1 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(resources.get::<Vec<usize>>().unwrap().is_empty());
+        assert_task_outcome(
+            root,
+            Some(TaskOutcome::Failed(TaskError::new("failed on purpose"))),
+            &mut world,
+            &mut resources,
+        );
+    }
+
+    #[test]
+    fn graph_progress_averages_over_tasks() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            fork!(@Steps::new(2), @Steps::new(4))
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        let mut progress = Vec::new();
+        for _ in 0..5 {
+            progress.push(handle.progress(&world));
+            schedule.execute(&mut world, &mut resources);
+        }
+        progress.push(handle.progress(&world));
+
+        assert_eq!(progress, vec![0.0, 0.0, 0.375, 0.75, 0.875, 1.0]);
+        assert!(handle.is_complete(&world));
+    }
+
+    #[test]
+    fn graph_progress_ignores_progress_that_is_not_a_number() {
+        #[derive(Clone, Debug)]
+        struct Unsure;
+
+        impl<'a> TaskComponent<'a> for Unsure {
+            type Data = ();
+
+            fn run(&mut self, _data: &mut Self::Data, ctx: &mut TaskContext) -> TaskStatus {
+                ctx.report_progress(f32::NAN);
+                TaskStatus::Running
+            }
+        }
+
+        let (mut world, mut resources, _) = set_up();
+        let mut schedule = Schedule::builder()
+            .add_system(
+                SystemBuilder::new("unsure_task_runner")
+                    .read_resource::<TaskClock>()
+                    .with_query(task_runner_query::<Unsure>())
+                    .build(|cmd, world, clock, task_query| {
+                        run_tasks(cmd, world, clock, &mut (), task_query)
+                    }),
+            )
+            .add_system(build_steps_task_runner_system())
+            .add_system(build_task_manager_system("task_manager"))
+            .build();
+
+        fn make_task_graph() -> TaskGraph {
+            fork!(@Steps::new(2), @Unsure)
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        schedule.execute(&mut world, &mut resources);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(handle.progress(&world), 0.25);
+    }
+
+    #[test]
+    fn events_of_graphs_maintained_in_parallel_stay_together() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Steps::new(2), @PushValue { value: 1 })
+        }
+        let mut graph_of = HashMap::new();
+        let mut handles = Vec::new();
+        for i in 0..16 {
+            let handle = assemble_task_graph_handle(
+                make_task_graph,
+                OnCompletion::Delete,
+                OnFailure::Abort,
+                &mut world,
+                &mut resources,
+            );
+            let root = handle.entity();
+            let first = world
+                .get_component::<components::SingleEdge>(root)
+                .unwrap()
+                .child;
+            graph_of.insert(root, i);
+            graph_of.insert(first, i);
+            handles.push(handle);
+        }
+
+        for _ in 0..5 {
+            let events = execute_and_collect_events(1, &mut schedule, &mut world, &mut resources);
+            let mut graphs: Vec<usize> = events
+                .iter()
+                .map(|event| graph_of[&event.entity()])
+                .collect();
+            graphs.dedup();
+            let num_graphs = graphs.len();
+            graphs.sort_unstable();
+            graphs.dedup();
+            assert_eq!(graphs.len(), num_graphs);
+        }
+
+        assert!(handles.iter().all(|handle| handle.is_complete(&world)));
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1; 16]);
+    }
+
+    #[test]
+    fn graphs_finish_independently_while_another_waits() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_waiting_graph() -> TaskGraph {
+            seq!(@Steps::new(8), @PushValue { value: 3 })
+        }
+        fn make_short_graph() -> TaskGraph {
+            seq!(@PushValue { value: 1 }, @PushValue { value: 2 })
+        }
+        let waiting = assemble_task_graph_handle(
+            make_waiting_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        let first = assemble_task_graph_handle(
+            make_short_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(first.is_complete(&world));
+        assert!(!waiting.is_finished(&world));
+
+        // Graphs assembled after the manager has seen the waiting one are picked up too.
+        let second = assemble_task_graph_handle(
+            make_short_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(second.is_complete(&world));
+        assert!(!waiting.is_finished(&world));
+
+        for _ in 0..4 {
+            schedule.execute(&mut world, &mut resources);
+        }
+        assert!(waiting.is_complete(&world));
+        assert_eq!(*resources.get::<Vec<usize>>().unwrap(), vec![1, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn weighted_graph_progress_counts_sequences_and_retries() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(@Steps::new(2), retry!(2, @Steps::new(4)))
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..5 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        // The first task is done, and the one inside the retry has run once.
+        assert_eq!(graph_progress(&world, root), 0.625);
+        let weighted = weighted_graph_progress(&world, root, |entity| {
+            if world.get_component::<Steps>(entity).is_some() {
+                1.0
+            } else {
+                3.0
+            }
+        });
+        assert_eq!(weighted, 0.4375);
+    }
+
+    #[test]
+    fn context_describes_task_and_graph() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @PushValue { value: 1 },
+                @Inspect { runs_left: 2 },
+                @PushValue { value: 2 }
+            )
+        }
+        let root = assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            advance_clock(std::time::Duration::default(), &mut resources);
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        let inspect = <Read<Inspect>>::query()
+            .iter_entities(&world)
+            .next()
+            .unwrap()
+            .0;
+        let inspections = resources.get::<Vec<Inspection>>().unwrap();
+        assert_eq!(
+            *inspections,
+            vec![
+                Inspection {
+                    entity: inspect,
+                    root,
+                    tick: 3,
+                    unblocked_tick: 3,
+                },
+                Inspection {
+                    entity: inspect,
+                    root,
+                    tick: 4,
+                    unblocked_tick: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn par_tasks_share_data_and_call_hooks_after_running() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @PushValue { value: 1 },
+                @PushValue { value: 2 },
+                fork!(@SumValues::new(2), @SumValues::new(2), @SumValues::new(1)),
+                @SumValues::new(1)
+            )
+        }
+        let handle = assemble_task_graph_handle(
+            make_task_graph,
+            OnCompletion::Delete,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..8 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert!(handle.is_complete(&world));
+        // Tasks that finish at the same time don't see what the others push when they finish.
+        assert_eq!(
+            *resources.get::<Vec<usize>>().unwrap(),
+            vec![1, 2, 3, 6, 6, 18]
+        );
+        assert_eq!(
+            handle.output(&world).unwrap().get::<usize>().copied(),
+            Some(18)
+        );
+    }
+
+    #[test]
+    fn runner_calls_lifecycle_hooks() {
+        let (mut world, mut resources, mut schedule) = set_up();
+
+        fn make_task_graph() -> TaskGraph {
+            seq!(
+                @Staged { runs_left: 2, fails: false },
+                @Staged { runs_left: 1, fails: true }
+            )
+        }
+        assemble_task_graph(
+            make_task_graph,
+            OnCompletion::None,
+            OnFailure::Abort,
+            &mut world,
+            &mut resources,
+        );
+
+        for _ in 0..6 {
+            schedule.execute(&mut world, &mut resources);
+        }
+
+        assert_eq!(
+            *resources.get::<Vec<&'static str>>().unwrap(),
+            vec![
+                "unblocked",
+                "run",
+                "run",
+                "complete",
+                "unblocked",
+                "run",
+                "not complete"
+            ]
+        );
+    }
 }
@@ -1,163 +1,1470 @@
-use crate::components::{FinalTag, MultiEdge, OnCompletion, SingleEdge, TaskProgress};
+use crate::components::{
+    BranchNode, CallbacksSlot, CancelHook, FinalTag, InnerEdge, Join, MultiEdge, OnCompletion,
+    OnFailure, OnQuorum, OnTimeout, PendingDecision, Predicate, RepeatNode, RetryNode, RunnableTag,
+    SingleEdge, TaskError, TaskOutcome, TaskOutput, TaskProgress, TimeoutNode, Unblocked,
+    WhileNode,
+};
+use crate::events::{TaskEvent, TaskEvents};
+use crate::graph_builder::{Cons, SharedGraph, TaskGraph};
+use crate::handle::{FinishedGraph, HandleSlot};
 
-use legion::{prelude::*, systems::SystemId};
+use legion::{
+    prelude::*, query::DefaultFilter, query::Query, storage::Component, systems::SystemId,
+};
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
 
-/// Returns true iff the task was seen as complete on the last run of the `TaskManagerSystem`.
-pub fn task_is_complete(world: &SubWorld, entity: Entity) -> bool {
-    if let Some(progress) = world.get_component::<TaskProgress>(entity) {
-        progress.is_complete()
-    } else {
-        // Task entity may not have a TaskProgress component yet if it's constructed lazily.
-        false
+/// Everything that stays the same while maintaining one graph.
+#[derive(Clone, Copy)]
+struct Traversal<'a> {
+    on_failure: OnFailure,
+    // The final entity of the graph.
+    root: Entity,
+    // The entity whose edge led to the one being maintained.
+    from: Option<Entity>,
+    // Number of times the task manager system has run.
+    tick: u64,
+    time: Instant,
+    events: &'a EventLog,
+    frontier: &'a RefCell<GraphFrontier>,
+    // The graphs that aren't being maintained by this worker, by final entity.
+    other_graphs: &'a HashMap<Entity, GraphFrontier>,
+    // While set, the entities reached from the one being maintained are collected here, along with
+    // the entity that reached them, instead of being maintained straight away.
+    reached: Option<&'a Reached>,
+}
+
+impl Traversal<'_> {
+    /// Whether an entity reached while maintaining the current one was put off until later, so the
+    /// outcomes seen so far aren't enough to decide anything.
+    fn is_putting_off(&self) -> bool {
+        self.reached
+            .is_some_and(|reached| !reached.borrow().is_empty())
     }
 }
 
-/// Returns true iff all of `entity`'s children are complete.
-pub fn fork_is_complete(world: &SubWorld, multi_children: &[Entity]) -> bool {
-    // We know that a fork's SingleEdge child is complete if any of the MultiEdge children are
-    // complete.
-    for child in multi_children.iter() {
-        if !entity_is_complete(world, *child) {
-            return false;
+/// Everything that the graphs maintained during one run of the task manager system share.
+struct Run<'a> {
+    tick: u64,
+    time: Instant,
+    other_graphs: &'a HashMap<Entity, GraphFrontier>,
+}
+
+/// Events recorded while maintaining one graph.
+type EventLog = RefCell<Vec<TaskEvent>>;
+
+/// Entities put off while maintaining another entity, each with the entity that reached it.
+type Reached = RefCell<Vec<(Entity, Option<Entity>)>>;
+
+/// Commands for the system's `CommandBuffer`, and writes to the components of maintained entities,
+/// recorded while graphs are maintained in parallel. They are applied in the order they were
+/// recorded once maintenance is over, so each graph sees its own commands flushed in order.
+#[derive(Default)]
+struct DeferredCommands(Vec<DeferredCommand>);
+
+type DeferredCommand = Box<dyn FnOnce(&mut CommandBuffer, &mut SubWorld) + Send>;
+
+impl DeferredCommands {
+    fn push<F>(&mut self, command: F)
+    where
+        F: 'static + FnOnce(&mut CommandBuffer, &mut SubWorld) + Send,
+    {
+        self.0.push(Box::new(command));
+    }
+
+    fn exec_mut<F: 'static + Fn(&mut World) + Send>(&mut self, f: F) {
+        self.push(move |cmd, _| cmd.exec_mut(f));
+    }
+
+    fn delete(&mut self, entity: Entity) {
+        self.push(move |cmd, _| cmd.delete(entity));
+    }
+
+    fn remove_component<C: Component>(&mut self, entity: Entity) {
+        self.push(move |cmd, _| cmd.remove_component::<C>(entity));
+    }
+
+    fn apply(self, cmd: &mut CommandBuffer, world: &mut SubWorld) {
+        for command in self.0.into_iter() {
+            command(cmd, world);
         }
     }
+}
 
-    true
+/// What the task manager remembers between runs, so that it only needs to maintain the entities
+/// that can make progress, instead of traversing every graph from its final entity. An entity that
+/// has been maintained once can only finish after one of its descendents does, except for tasks and
+/// control entities, which are kept in the ready set once they are unblocked. When one of those
+/// finishes, the manager follows parent links to maintain the entities that depend on it.
+///
+/// Each graph has a `GraphFrontier` of its own, which the worker that maintains the graph owns
+/// while it runs, so that graphs maintained in parallel don't wait on each other.
+#[derive(Default)]
+struct Frontier {
+    // The graphs that aren't being maintained right now, by final entity.
+    graphs: HashMap<Entity, GraphFrontier>,
+    // Graphs with entities to maintain on the next run.
+    ready_graphs: HashSet<Entity>,
+    // Graphs with entities to maintain on the current run.
+    due_graphs: Vec<Entity>,
+    // Counts the graphs the task manager has seen, to keep them in that order.
+    num_seen: u64,
+}
+
+/// The part of the `Frontier` that belongs to one graph.
+struct GraphFrontier {
+    // The final entity of the graph, and how the graph is maintained.
+    root: Entity,
+    final_tag: FinalTag,
+    // When the task manager first saw the graph, relative to the others.
+    order: u64,
+    // Every entity that has been maintained and wasn't reported as finished or deleted yet.
+    nodes: HashMap<Entity, Node>,
+    // Entities to maintain on the next run, in the order they became ready.
+    ready: Vec<Entity>,
+    is_ready: HashSet<Entity>,
+    // Entities to maintain on the current run.
+    due: Vec<Entity>,
+    // Entities maintained from the frontier during the current run.
+    maintained: HashSet<Entity>,
+    // Final entities of other graphs that were appended to this one, and the entity each was
+    // reached from. Their frontiers are merged into this one after the run.
+    appended: Vec<(Entity, Entity)>,
+    is_finished: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    // `None` for final entities.
+    parent: Option<Entity>,
+}
+
+impl Frontier {
+    /// Makes the entities that became ready during the last run due on this one.
+    fn start_run(&mut self) {
+        for root in self.ready_graphs.drain() {
+            if let Some(graph) = self.graphs.get_mut(&root) {
+                graph.maintained.clear();
+                graph.is_ready.clear();
+                graph.due = std::mem::take(&mut graph.ready);
+                self.due_graphs.push(root);
+            }
+        }
+    }
+
+    /// Starts keeping track of a graph that the task manager hasn't seen before.
+    fn new_graph(&mut self, root: Entity, final_tag: FinalTag) -> GraphFrontier {
+        self.num_seen += 1;
+        GraphFrontier {
+            root,
+            final_tag,
+            order: self.num_seen,
+            nodes: HashMap::new(),
+            ready: Vec::new(),
+            is_ready: HashSet::new(),
+            due: Vec::new(),
+            maintained: HashSet::new(),
+            appended: Vec::new(),
+            is_finished: false,
+        }
+    }
+
+    /// Takes the graphs with due entities, in the order the task manager first saw them.
+    fn take_due_graphs(&mut self) -> Vec<GraphFrontier> {
+        let mut graphs: Vec<GraphFrontier> = std::mem::take(&mut self.due_graphs)
+            .into_iter()
+            .filter_map(|root| self.graphs.remove(&root))
+            .collect();
+        graphs.sort_by_key(|graph| graph.order);
+
+        graphs
+    }
+
+    /// Puts back the graphs that still have entities to maintain. A graph that was appended to another one takes
+    /// over the frontier of that graph first.
+    fn keep(&mut self, maintained: Vec<(GraphFrontier, Vec<TaskEvent>)>) {
+        for (mut graph, events) in maintained.into_iter() {
+            for (appended, parent) in std::mem::take(&mut graph.appended).into_iter() {
+                if let Some(other) = self.graphs.remove(&appended) {
+                    if !other.due.is_empty() {
+                        self.due_graphs.push(graph.root);
+                    }
+                    graph.merge(other, parent);
+                }
+            }
+            graph.forget(&events);
+            // A finished graph is kept until the prongs left running by its quorums finish.
+            if graph.is_finished && graph.ready.is_empty() {
+                continue;
+            }
+            if !graph.ready.is_empty() {
+                self.ready_graphs.insert(graph.root);
+            }
+            self.graphs.insert(graph.root, graph);
+        }
+    }
+}
+
+impl GraphFrontier {
+    /// Records that `entity` was reached through an edge from `parent`. Returns `true` if it had
+    /// been maintained before, which includes the final entities of graphs that were appended to.
+    fn visit(
+        &mut self,
+        entity: Entity,
+        parent: Option<Entity>,
+        other_graphs: &HashMap<Entity, GraphFrontier>,
+    ) -> bool {
+        if self.nodes.contains_key(&entity) {
+            return true;
+        }
+        if let (Some(parent), true) = (parent, other_graphs.contains_key(&entity)) {
+            // This graph was appended to the one that ended at `entity`.
+            self.appended.push((entity, parent));
+            return true;
+        }
+        self.nodes.insert(entity, Node { parent });
+
+        false
+    }
+
+    fn is_known(&self, entity: Entity, other_graphs: &HashMap<Entity, GraphFrontier>) -> bool {
+        self.nodes.contains_key(&entity) || other_graphs.contains_key(&entity)
+    }
+
+    fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.nodes.get(&entity).and_then(|node| node.parent)
+    }
+
+    /// Takes over the entities of a graph that was appended to this one, whose final entity now
+    /// depends on `parent`.
+    fn merge(&mut self, other: GraphFrontier, parent: Entity) {
+        self.nodes.extend(other.nodes);
+        self.nodes.insert(
+            other.root,
+            Node {
+                parent: Some(parent),
+            },
+        );
+        self.due.extend(other.due);
+        for entity in other.ready.into_iter() {
+            self.mark_ready(entity);
+        }
+    }
+
+    fn mark_ready(&mut self, entity: Entity) {
+        if self.is_ready.insert(entity) {
+            self.ready.push(entity);
+        }
+    }
+
+    /// Forgets the entities that were reported as finished or deleted.
+    fn forget(&mut self, events: &[TaskEvent]) {
+        for event in events.iter() {
+            match event {
+                TaskEvent::Finished(entity, _) | TaskEvent::Deleted(entity) => {
+                    self.nodes.remove(entity);
+                    self.is_ready.remove(entity);
+                }
+                _ => (),
+            }
+        }
+        let is_ready = &self.is_ready;
+        self.ready.retain(|entity| is_ready.contains(entity));
+    }
 }
 
 /// Tells you whether a fork or a task entity is complete.
 pub fn entity_is_complete(world: &SubWorld, entity: Entity) -> bool {
-    // Only fork entities can have `MultiEdge`s. If the entity is being created lazily, we won't
-    // know if it's a fork or task, but we won't consider it complete regardless.
-    if let Some(edge) = world.get_component::<MultiEdge>(entity) {
-        fork_is_complete(world, &edge.children)
+    entity_outcome(world, entity) == Some(TaskOutcome::Complete)
+}
+
+/// Tells you how a fork or a task entity finished, or `None` if it hasn't finished yet. Forks are
+/// only seen as finished after the `TaskManagerSystem` has run.
+pub fn entity_outcome(world: &SubWorld, entity: Entity) -> Option<TaskOutcome> {
+    // Task entity may not have a TaskProgress component yet if it's constructed lazily.
+    world
+        .get_component::<TaskProgress>(entity)
+        .and_then(|progress| progress.outcome())
+}
+
+/// Returns the output of a task or control entity that completed, if it produced one.
+pub fn entity_output(world: &SubWorld, entity: Entity) -> Option<TaskOutput> {
+    world
+        .get_component::<TaskProgress>(entity)
+        .and_then(|progress| progress.output())
+}
+
+/// Returns how far along the graph that ends at `entity` is, from 0.0 to 1.0. Every task counts the
+/// same, as does every control node like a retry, which counts as far along as the sub-graph it
+/// owns. Forks only count through their prongs. Finished entities count as done, whether they
+/// completed or not.
+pub fn graph_progress<W: EntityStore>(world: &W, entity: Entity) -> f32 {
+    weighted_graph_progress(world, entity, |_| 1.0)
+}
+
+/// Like `graph_progress`, but each task and control entity counts as much as `weight` says.
+pub fn weighted_graph_progress<W, F>(world: &W, entity: Entity, weight: F) -> f32
+where
+    W: EntityStore,
+    F: Fn(Entity) -> f32,
+{
+    fraction_of(sum_progress(world, entity, &weight))
+}
+
+fn fraction_of((done, total): (f32, f32)) -> f32 {
+    if total > 0.0 {
+        done / total
     } else {
-        task_is_complete(world, entity)
+        0.0
     }
 }
 
-/// Deletes only the descendent entities of `entity`, but leaves `entity` alive.
-pub fn delete_descendents(cmd: &CommandBuffer, world: &SubWorld, entity: Entity) {
-    if let Some(edge) = world.get_component::<MultiEdge>(entity) {
-        for child in edge.children.iter() {
-            delete_entity_and_descendents(cmd, world, *child);
+/// A step of `sum_progress`.
+enum ProgressStep {
+    /// Add the progress of an entity and its descendents to the innermost sum.
+    Sum(Entity),
+    /// Finish the innermost sum, which belongs to the sub-graph of a control node with the given
+    /// weight, and add it to the sum around it as that node's progress.
+    Scale(f32),
+}
+
+/// Returns the weighted work done by `entity` and its descendents, and their total weight.
+fn sum_progress<W, F>(world: &W, entity: Entity, weight: &F) -> (f32, f32)
+where
+    W: EntityStore,
+    F: Fn(Entity) -> f32,
+{
+    // Graphs can be too deep to recurse through. The sub-graph of a control node counts as a
+    // fraction of the node's weight, so it is summed separately.
+    let mut sums = vec![(0.0, 0.0)];
+    let mut stack = vec![ProgressStep::Sum(entity)];
+    while let Some(step) = stack.pop() {
+        let entity = match step {
+            ProgressStep::Sum(entity) => entity,
+            ProgressStep::Scale(weight) => {
+                let inner = sums.pop().unwrap();
+                let (done, total) = sums.last_mut().unwrap();
+                *done += weight * fraction_of(inner);
+                *total += weight;
+                continue;
+            }
+        };
+        let fraction = match world.get_component::<TaskProgress>(entity) {
+            Some(progress) => progress.fraction(),
+            None => continue,
+        };
+        if let Some(edge) = world.get_component::<SingleEdge>(entity) {
+            stack.push(ProgressStep::Sum(edge.child));
+        }
+        if let Some(multi_edge) = world.get_component::<MultiEdge>(entity) {
+            stack.extend(
+                multi_edge
+                    .children
+                    .iter()
+                    .map(|&prong| ProgressStep::Sum(prong)),
+            );
+            continue;
+        }
+        match inner_child(world, entity) {
+            Some(child) if fraction < 1.0 => {
+                stack.push(ProgressStep::Scale(weight(entity)));
+                stack.push(ProgressStep::Sum(child));
+                sums.push((0.0, 0.0));
+            }
+            _ => {
+                let weight = weight(entity);
+                let (done, total) = sums.last_mut().unwrap();
+                *done += weight * fraction;
+                *total += weight;
+            }
         }
     }
+
+    sums.pop().unwrap()
+}
+
+/// Returns the last entity of the sub-graph owned by `entity`, if any.
+fn inner_child<W: EntityStore>(world: &W, entity: Entity) -> Option<Entity> {
+    world
+        .get_component::<InnerEdge>(entity)
+        .and_then(|edge| edge.child)
+}
+
+/// Returns every entity that `entity` has an edge to.
+fn children<W: EntityStore>(world: &W, entity: Entity) -> Vec<Entity> {
+    let mut children = Vec::new();
+    if let Some(edge) = world.get_component::<MultiEdge>(entity) {
+        children.extend(edge.children.iter().copied());
+    }
+    children.extend(inner_child(world, entity));
     if let Some(edge) = world.get_component::<SingleEdge>(entity) {
-        delete_entity_and_descendents(cmd, world, edge.child);
+        children.push(edge.child);
     }
+
+    children
 }
 
-/// Deletes `entity` and all of its descendents.
-pub fn delete_entity_and_descendents(cmd: &CommandBuffer, world: &SubWorld, entity: Entity) {
-    // Support async deletion. If a child is deleted, we assume all of its descendants were also
-    // deleted.
-    if !world.is_alive(entity) {
-        return;
+/// Deletes `entity` and all of its descendents, each one after its own descendents.
+fn delete_entity_and_descendents(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    events: &EventLog,
+) {
+    // Graphs can be too deep to recurse through, so entities wait on the stack until their
+    // descendents have been deleted.
+    let mut stack = vec![(entity, false)];
+    while let Some((entity, is_expanded)) = stack.pop() {
+        if is_expanded {
+            log::debug!("Deleting {:?}", entity);
+            cmd.delete(entity);
+            events.borrow_mut().push(TaskEvent::Deleted(entity));
+            continue;
+        }
+        // Support async deletion. If a child is deleted, we assume all of its descendants were
+        // also deleted.
+        if world.is_alive(entity) {
+            stack.push((entity, true));
+            stack.extend(
+                children(world, entity)
+                    .into_iter()
+                    .rev()
+                    .map(|c| (c, false)),
+            );
+        }
     }
-
-    delete_descendents(cmd, world, entity);
-    log::debug!("Deleting {:?}", entity);
-    cmd.delete(entity);
 }
 
-/// Returns `true` iff `entity` is complete.
-fn maintain_task_and_descendents(
-    cmd: &CommandBuffer,
-    world: &mut SubWorld,
+/// Cancels `entity` and all of its descendents that haven't finished yet. The `on_cancel` hooks
+/// that need to be called are pushed onto `hooks`.
+fn cancel_entity_and_descendents<W: EntityStore>(
+    world: &W,
     entity: Entity,
-) -> bool {
-    let (is_unblocked, is_complete) =
+    hooks: &mut Vec<(Entity, CancelHook)>,
+) {
+    cancel_entities(world, vec![entity], hooks);
+}
+
+/// Cancels every unfinished prong of `entity` if it's a fork or a race.
+fn cancel_prongs<W: EntityStore>(world: &W, entity: Entity, hooks: &mut Vec<(Entity, CancelHook)>) {
+    if let Some(edge) = world.get_component::<MultiEdge>(entity) {
+        cancel_entities(world, edge.children.clone(), hooks);
+    }
+}
+
+/// Cancels each of `entities` and their descendents in order, using an explicit stack so that
+/// long sequences can't overflow the call stack.
+fn cancel_entities<W: EntityStore>(
+    world: &W,
+    entities: Vec<Entity>,
+    hooks: &mut Vec<(Entity, CancelHook)>,
+) {
+    let mut stack = entities;
+    stack.reverse();
+    while let Some(entity) = stack.pop() {
         if let Some(progress) = world.get_component::<TaskProgress>(entity) {
-            (progress.is_unblocked, progress.is_complete())
-        } else {
-            // Missing progress means the task is complete and progress was already removed.
-            return true;
-        };
+            // Everything below a finished entity has also finished, except prongs left running by
+            // a quorum.
+            if progress.is_finished() {
+                if let Some(edge) = world.get_component::<MultiEdge>(entity) {
+                    stack.extend(edge.prongs_left_running().iter().rev());
+                }
+                continue;
+            }
+            log::debug!("Cancelling {:?}", entity);
+            progress.cancel();
+            if let Some(hook) = progress.cancel_hook() {
+                hooks.push((entity, hook));
+            }
+        }
+        // Prongs first, then the sub-graph of a control entity, then the entity it depends on.
+        stack.extend(children(world, entity).into_iter().rev());
+    }
+}
 
-    if is_complete {
-        log::debug!(
-            "Noticed task {:?} is complete, removing TaskProgress",
-            entity
-        );
-        return true;
+/// Submits commands to call the `on_cancel` hooks of cancelled tasks. Only tasks that were
+/// unblocked have hooks, so this is also where they stop being runnable.
+fn submit_cancel_hooks(cmd: &mut DeferredCommands, hooks: Vec<(Entity, CancelHook)>) {
+    for (entity, hook) in hooks.into_iter() {
+        cmd.exec_mut(move |world| call_cancel_hook(world, entity, hook));
     }
+}
 
-    // If `is_unblocked`, the children don't need maintenance, because we already verified they
-    // are all complete.
-    if is_unblocked {
-        return false;
+/// Lets a task's runner find it. A task cancelled by a command that was flushed first has already
+/// had its tag removed by `call_cancel_hook`, so it isn't given one again.
+fn add_runnable_tag(world: &mut World, entity: Entity) {
+    let is_finished = match world.get_component::<TaskProgress>(entity) {
+        Some(progress) => progress.is_finished(),
+        // The task was deleted in the meantime.
+        None => return,
+    };
+    if !is_finished {
+        let _ = world.add_component(entity, RunnableTag);
     }
+}
 
-    // Unblock the task if its child is complete.
-    let mut child_complete = true;
-    if let Some(edge) = world
-        .get_component::<SingleEdge>(entity)
-        .map(|e| (*e).clone())
-    {
-        child_complete = maintain_entity_and_descendents(cmd, world, edge.child);
+fn call_cancel_hook(world: &mut World, entity: Entity, hook: CancelHook) {
+    hook(world, entity);
+    // Fails if the task was deleted in the meantime.
+    let _ = world.remove_component::<RunnableTag>(entity);
+}
+
+/// Cancels every unfinished task in the graph rooted at `entity`, so runners stop running them, and
+/// calls their `TaskComponent::on_cancel` hooks. If `entity` is final, the task manager will clean
+/// up the graph according to its `OnCompletion`. Otherwise the tasks that depend on `entity` are
+/// cancelled as well.
+pub fn cancel(cmd: &CommandBuffer, entity: Entity) {
+    cmd.exec_mut(move |world| {
+        let mut hooks = Vec::new();
+        cancel_entity_and_descendents(world, entity, &mut hooks);
+        for (task, hook) in hooks.into_iter() {
+            call_cancel_hook(world, task, hook);
+        }
+    });
+    log::debug!("Submitted command to cancel {:?}", entity);
+}
+
+/// Records `outcome` for an entity whose outcome is decided by the manager rather than a runner.
+fn finish_entity(world: &SubWorld, entity: Entity, outcome: TaskOutcome) -> Option<TaskOutcome> {
+    log::debug!("Entity {:?} finished: {:?}", entity, outcome);
+    world
+        .get_component::<TaskProgress>(entity)
+        .expect("Maintained entity must have progress")
+        .finish(outcome.clone());
+
+    Some(outcome)
+}
+
+/// Like `finish_entity`, but if `inner` completed, its output also becomes the output of `entity`.
+fn finish_entity_like(
+    world: &SubWorld,
+    entity: Entity,
+    inner: Entity,
+    outcome: TaskOutcome,
+) -> Option<TaskOutcome> {
+    if outcome == TaskOutcome::Complete {
+        pass_on_output(world, inner, entity);
+    }
+    finish_entity(world, entity, outcome)
+}
+
+fn pass_on_output(world: &SubWorld, from: Entity, to: Entity) {
+    world
+        .get_component::<TaskProgress>(to)
+        .expect("Maintained entity must have progress")
+        .set_output(entity_output(world, from));
+}
+
+/// Returns the outcome of a fork once enough of its prongs have completed, or once it's clear that
+/// they can't.
+fn maintain_prongs(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    edge: &MultiEdge,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    let on_failure = traversal.on_failure;
+    let (needed, cancel_rest) = match edge.join {
+        Join::All => (edge.children.len(), false),
+        Join::Quorum { needed, rest } => (needed, rest == OnQuorum::CancelRest),
+    };
+    let mut num_complete = 0;
+    let mut num_unfinished = 0;
+    let mut failure = None;
+    let mut first_complete = None;
+    for child in edge.children.iter() {
+        match maintain_entity_and_descendents(cmd, world, *child, traversal) {
+            None => num_unfinished += 1,
+            Some(TaskOutcome::Complete) => {
+                num_complete += 1;
+                first_complete.get_or_insert(*child);
+            }
+            Some(TaskOutcome::Failed(_)) if on_failure == OnFailure::Continue => num_complete += 1,
+            Some(TaskOutcome::Failed(error)) => {
+                if failure.is_none() {
+                    failure = Some(error);
+                }
+            }
+            Some(TaskOutcome::Cancelled) => (),
+        }
     }
-    if child_complete {
-        log::debug!("Unblocking task {:?}", entity);
-        let mut progress = world
-            .get_component_mut::<TaskProgress>(entity)
-            .expect("Blocked task must have progress");
-        progress.unblock();
+    if traversal.is_putting_off() {
+        return None;
     }
 
-    false
+    if num_complete >= needed {
+        if cancel_rest {
+            let mut hooks = Vec::new();
+            cancel_prongs(world, entity, &mut hooks);
+            submit_cancel_hooks(cmd, hooks);
+        }
+        if let (1, Some(winner)) = (needed, first_complete) {
+            return finish_entity_like(world, entity, winner, TaskOutcome::Complete);
+        }
+        return finish_entity(world, entity, TaskOutcome::Complete);
+    }
+    if num_complete + num_unfinished >= needed {
+        return None;
+    }
+
+    // Too many prongs failed or were cancelled for the fork to complete.
+    if let Some(error) = failure {
+        if on_failure == OnFailure::Abort {
+            // Fail fast.
+            let mut hooks = Vec::new();
+            cancel_prongs(world, entity, &mut hooks);
+            submit_cancel_hooks(cmd, hooks);
+        } else if num_unfinished > 0 {
+            return None;
+        }
+        finish_entity(world, entity, TaskOutcome::Failed(error))
+    } else if num_unfinished > 0 {
+        None
+    } else {
+        finish_entity(world, entity, TaskOutcome::Cancelled)
+    }
 }
 
-/// Returns `true` iff `entity` is complete.
-fn maintain_fork_and_descendents(
-    cmd: &CommandBuffer,
-    world: &mut SubWorld,
+/// Keeps maintaining the prongs of a finished quorum that left them running.
+fn maintain_prongs_left_running(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
     entity: Entity,
-    multi_edge_children: &[Entity],
-) -> bool {
-    // We make sure that the SingleEdge child completes before any of the MultiEdge descendents
-    // can start.
-    let mut single_child_complete = true;
-    if let Some(edge) = world
-        .get_component::<SingleEdge>(entity)
-        .map(|e| (*e).clone())
-    {
-        single_child_complete = maintain_entity_and_descendents(cmd, world, edge.child);
+    traversal: Traversal<'_>,
+) {
+    let prongs = match world.get_component::<MultiEdge>(entity) {
+        Some(edge) => edge.prongs_left_running().to_vec(),
+        None => return,
+    };
+    for prong in prongs.into_iter() {
+        maintain_entity_and_descendents(cmd, world, prong, traversal);
     }
-    let mut multi_children_complete = true;
-    if single_child_complete {
-        for child in multi_edge_children.iter() {
-            multi_children_complete &= maintain_entity_and_descendents(cmd, world, *child);
+}
+
+/// Returns the outcome of a retry entity once it has succeeded or run out of attempts.
+fn maintain_retry(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if let Some(instance) = inner_child(world, entity) {
+        match maintain_entity_and_descendents(cmd, world, instance, traversal)? {
+            TaskOutcome::Failed(error) => {
+                let attempts_left = world
+                    .get_component::<RetryNode>(entity)
+                    .expect("Retry entity must have a RetryNode")
+                    .attempts_left;
+                if attempts_left == 0 {
+                    return finish_entity(world, entity, TaskOutcome::Failed(error));
+                }
+                log::debug!("Retrying {:?} after failure: {}", entity, error);
+                delete_entity_and_descendents(cmd, world, instance, traversal.events);
+            }
+            outcome => return finish_entity_like(world, entity, instance, outcome),
         }
     }
 
-    single_child_complete && multi_children_complete
+    // Components can only be written to once graphs are no longer maintained in parallel.
+    cmd.push(move |cmd, world| {
+        let graph = {
+            let mut node = world
+                .get_component_mut::<RetryNode>(entity)
+                .expect("Retry entity must have a RetryNode");
+            node.attempts_left -= 1;
+            node.graph.clone()
+        };
+        assemble_instance(cmd, world, entity, &graph);
+    });
+
+    None
 }
 
-/// Returns `true` iff `entity` is complete.
-fn maintain_entity_and_descendents(
-    cmd: &CommandBuffer,
+/// Returns the outcome of a branch entity once its chosen side finishes. The predicate can only be
+/// evaluated on the whole `World`, so the side is chosen and assembled by a command, or by
+/// `evaluate_task_predicates` if the predicate needs resources.
+fn maintain_branch(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if let Some(side) = inner_child(world, entity) {
+        let outcome = maintain_entity_and_descendents(cmd, world, side, traversal)?;
+        return finish_entity_like(world, entity, side, outcome);
+    }
+
+    let node = world
+        .get_component::<BranchNode>(entity)
+        .expect("Branch entity must have a BranchNode");
+    if !node.is_deciding {
+        let predicate = node.predicate.clone();
+        cmd.push(move |cmd, world| {
+            world
+                .get_component_mut::<BranchNode>(entity)
+                .expect("Branch entity must have a BranchNode")
+                .is_deciding = true;
+            submit_decision(cmd, entity, predicate, decide_branch);
+        });
+        log::debug!("Submitted command to decide branch {:?}", entity);
+    }
+
+    None
+}
+
+fn decide_branch(world: &mut World, entity: Entity, is_true: bool) {
+    let graph = match world.get_component::<BranchNode>(entity) {
+        Some(node) if is_true => node.then_graph.clone(),
+        Some(node) => node.else_graph.clone(),
+        // The branch was deleted before the predicate was evaluated.
+        None => return,
+    };
+    assemble_inner_graph(world, entity, &graph);
+}
+
+/// Submits a command that evaluates `predicate` and passes the result to `decide`. Predicates that
+/// need resources are left to `evaluate_task_predicates` instead.
+fn submit_decision(
+    cmd: &mut CommandBuffer,
+    entity: Entity,
+    predicate: Predicate,
+    decide: fn(&mut World, Entity, bool),
+) {
+    if predicate.needs_resources() {
+        cmd.add_component(entity, PendingDecision { predicate, decide });
+    } else {
+        cmd.exec_mut(move |world| {
+            let is_true = predicate.evaluate(world, None);
+            decide(world, entity, is_true);
+        });
+    }
+}
+
+/// Evaluates the predicates of branches and loops that were made with `Predicate::with_resources`,
+/// and assembles the graphs they choose. Add it to the schedule after the task manager system with
+/// `add_thread_local_fn` if any predicate needs resources; put a `flush` in between for the
+/// predicates to be evaluated in the same frame as the task manager decided to evaluate them.
+pub fn evaluate_task_predicates(world: &mut World, resources: &mut Resources) {
+    let pending: Vec<(Entity, PendingDecision)> = <Read<PendingDecision>>::query()
+        .iter_entities(world)
+        .map(|(entity, pending)| (entity, (*pending).clone()))
+        .collect();
+    for (entity, pending) in pending.into_iter() {
+        world.remove_component::<PendingDecision>(entity).unwrap();
+        let is_true = pending.predicate.evaluate(world, Some(resources));
+        (pending.decide)(world, entity, is_true);
+    }
+}
+
+/// Assembles a fresh copy of `graph` as the sub-graph of a retry or repeat entity.
+fn assemble_instance(
+    cmd: &mut CommandBuffer,
     world: &mut SubWorld,
     entity: Entity,
-) -> bool {
+    graph: &TaskGraph,
+) {
+    let (_first_entity, last_entity) = graph._assemble(None, cmd);
+    world
+        .get_component_mut::<InnerEdge>(entity)
+        .expect("Entity with an inner graph must have an InnerEdge")
+        .child = Some(last_entity);
+}
+
+/// Assembles `graph` as the sub-graph of `entity`, once a predicate has chosen it. If `graph` is
+/// empty, `entity` completes straight away.
+fn assemble_inner_graph(world: &mut World, entity: Entity, graph: &TaskGraph) {
+    // The entity may have been cancelled or deleted before the predicate was evaluated.
+    match world.get_component::<TaskProgress>(entity) {
+        Some(progress) if !progress.is_finished() => {
+            if let Cons::Nil = graph {
+                log::debug!("Entity {:?} finished: nothing to run", entity);
+                progress.complete();
+                return;
+            }
+        }
+        _ => return,
+    }
+
+    let mut cmd = CommandBuffer::new(world);
+    let (_first_entity, last_entity) = graph._assemble(None, &mut cmd);
+    cmd.write(world);
+    log::debug!("Entity {:?} assembled {:?}", entity, last_entity);
+    world
+        .get_component_mut::<InnerEdge>(entity)
+        .expect("Entity with an inner graph must have an InnerEdge")
+        .child = Some(last_entity);
+}
+
+/// Maintains the current iteration of a loop, deleting it once it completes. Returns `Ok` if the
+/// loop is ready for its next iteration, or else what the loop entity should return.
+fn maintain_iteration(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Result<(), Option<TaskOutcome>> {
+    if let Some(instance) = inner_child(world, entity) {
+        match maintain_entity_and_descendents(cmd, world, instance, traversal) {
+            None => return Err(None),
+            // The loop's output is the output of its last iteration.
+            Some(TaskOutcome::Complete) => pass_on_output(world, instance, entity),
+            Some(TaskOutcome::Failed(_)) if traversal.on_failure == OnFailure::Continue => (),
+            Some(outcome) => return Err(finish_entity(world, entity, outcome)),
+        }
+        delete_entity_and_descendents(cmd, world, instance, traversal.events);
+        cmd.push(move |_, world| {
+            world
+                .get_component_mut::<InnerEdge>(entity)
+                .expect("Loop entity must have an InnerEdge")
+                .child = None;
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns the outcome of a repeat entity once it has run all of its iterations, or one of them
+/// fails.
+fn maintain_repeat(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if let Err(outcome) = maintain_iteration(cmd, world, entity, traversal) {
+        return outcome;
+    }
+
+    let graph = {
+        let node = world
+            .get_component::<RepeatNode>(entity)
+            .expect("Repeat entity must have a RepeatNode");
+        if node.iterations_left == 0 {
+            None
+        } else {
+            Some(node.graph.clone())
+        }
+    };
+    let graph = match graph {
+        Some(graph) => graph,
+        None => return finish_entity(world, entity, TaskOutcome::Complete),
+    };
+    cmd.push(move |cmd, world| {
+        world
+            .get_component_mut::<RepeatNode>(entity)
+            .expect("Repeat entity must have a RepeatNode")
+            .iterations_left -= 1;
+        assemble_instance(cmd, world, entity, &graph);
+    });
+
+    None
+}
+
+/// Returns the outcome of a while entity once its predicate no longer holds, or one of its
+/// iterations fails. Like for a branch, the next iteration is assembled once the predicate has been
+/// evaluated outside of the task manager.
+fn maintain_while(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if let Err(outcome) = maintain_iteration(cmd, world, entity, traversal) {
+        return outcome;
+    }
+
+    let node = world
+        .get_component::<WhileNode>(entity)
+        .expect("While entity must have a WhileNode");
+    if !node.is_deciding {
+        let predicate = node.predicate.clone();
+        cmd.push(move |cmd, world| {
+            world
+                .get_component_mut::<WhileNode>(entity)
+                .expect("While entity must have a WhileNode")
+                .is_deciding = true;
+            submit_decision(cmd, entity, predicate, decide_while);
+        });
+        log::debug!("Submitted command to decide next iteration of {:?}", entity);
+    }
+
+    None
+}
+
+fn decide_while(world: &mut World, entity: Entity, is_true: bool) {
+    let next = match world.get_component::<WhileNode>(entity) {
+        Some(node) if is_true => node.graph.clone(),
+        Some(_) => SharedGraph(Arc::new(Cons::Nil)),
+        // The loop was deleted before the predicate was evaluated.
+        None => return,
+    };
+    assemble_inner_graph(world, entity, &next);
+    if let Some(mut node) = world.get_component_mut::<WhileNode>(entity) {
+        node.is_deciding = false;
+    }
+}
+
+/// Lets a task that awaits a graph it spawned run again once the graph completes. If the graph
+/// fails or is cancelled, returns the outcome that the task inherits from it.
+fn maintain_awaited(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    let awaited = world
+        .get_component::<TaskProgress>(entity)
+        .and_then(|progress| progress.awaited())?;
+    // Until the runner's commands are flushed, the awaited entity may not exist yet.
+    let edge = world.get_component::<SingleEdge>(entity).map(|e| e.child);
+    if edge != Some(awaited) {
+        return None;
+    }
+    match maintain_entity_and_descendents(cmd, world, awaited, traversal)? {
+        TaskOutcome::Complete => (),
+        TaskOutcome::Failed(_) if traversal.on_failure == OnFailure::Continue => (),
+        outcome => {
+            // The task stops without finishing on its own, just like a cancelled task.
+            let hook = world
+                .get_component::<TaskProgress>(entity)
+                .and_then(|progress| progress.cancel_hook());
+            submit_cancel_hooks(cmd, hook.map(|hook| (entity, hook)).into_iter().collect());
+            return finish_entity(world, entity, outcome);
+        }
+    }
+
+    log::debug!("Resuming {:?}", entity);
+    let input = entity_output(world, awaited);
+    world
+        .get_component::<TaskProgress>(entity)
+        .expect("Awaiting task must have progress")
+        .resume(input);
+
+    None
+}
+
+/// Returns the outcome of a timeout entity once its sub-graph finishes or time runs out.
+fn maintain_timeout(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    node: TimeoutNode,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    let inner = inner_child(world, entity).expect("Timeout entity must have an inner child");
+    if let Some(outcome) = maintain_entity_and_descendents(cmd, world, inner, traversal) {
+        return finish_entity_like(world, entity, inner, outcome);
+    }
+    if traversal.is_putting_off() {
+        return None;
+    }
+
+    let unblocked = world
+        .get_component::<TaskProgress>(entity)
+        .and_then(|progress| progress.unblocked.get().copied())
+        .expect("Timeout entity must be unblocked");
+    if !node.has_expired(unblocked, traversal.tick, traversal.time) {
+        return None;
+    }
+
+    log::debug!("Timeout {:?} expired", entity);
+    let mut hooks = Vec::new();
+    cancel_entity_and_descendents(world, inner, &mut hooks);
+    submit_cancel_hooks(cmd, hooks);
+    match node.on_timeout {
+        OnTimeout::Fail => finish_entity(
+            world,
+            entity,
+            TaskOutcome::Failed(TaskError::new("timed out")),
+        ),
+        OnTimeout::Cancel => finish_entity(world, entity, TaskOutcome::Cancelled),
+    }
+}
+
+/// Returns the outcome of `entity` if it has finished.
+fn maintain_entity(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    let traversal = Traversal {
+        from: Some(entity),
+        ..traversal
+    };
+    let (outcome, is_unblocked) = match world.get_component::<TaskProgress>(entity) {
+        Some(progress) => (progress.outcome(), progress.is_unblocked()),
+        // Task entity may not have a TaskProgress component yet if it's constructed lazily.
+        None => return None,
+    };
+    if let Some(outcome) = outcome {
+        maintain_prongs_left_running(cmd, world, entity, traversal);
+        return Some(outcome);
+    }
+
+    // If `is_unblocked`, the child doesn't need maintenance, because we already verified it's
+    // finished.
+    if !is_unblocked {
+        let (child_outcome, input) = if let Some(edge) = world
+            .get_component::<SingleEdge>(entity)
+            .map(|e| (*e).clone())
+        {
+            let outcome = maintain_entity_and_descendents(cmd, world, edge.child, traversal)?;
+            (outcome, entity_output(world, edge.child))
+        } else {
+            (TaskOutcome::Complete, None)
+        };
+        match child_outcome {
+            TaskOutcome::Complete => (),
+            TaskOutcome::Failed(_) if traversal.on_failure == OnFailure::Continue => (),
+            outcome => {
+                // None of the prongs can start without the child.
+                let mut hooks = Vec::new();
+                cancel_prongs(world, entity, &mut hooks);
+                submit_cancel_hooks(cmd, hooks);
+                return finish_entity(world, entity, outcome);
+            }
+        }
+
+        log::debug!("Unblocking {:?}", entity);
+        traversal
+            .events
+            .borrow_mut()
+            .push(TaskEvent::Unblocked(entity));
+        let is_task = {
+            let progress = world
+                .get_component::<TaskProgress>(entity)
+                .expect("Blocked task must have progress");
+            progress.unblock(
+                Unblocked {
+                    tick: traversal.tick,
+                    time: traversal.time,
+                    root: traversal.root,
+                },
+                input,
+            );
+            progress.is_task()
+        };
+        if is_task {
+            cmd.exec_mut(move |world| add_runnable_tag(world, entity));
+        }
+        // Forks only finish once their prongs do.
+        if world.get_component::<MultiEdge>(entity).is_none() {
+            traversal.frontier.borrow_mut().mark_ready(entity);
+        }
+    }
+
     // Only fork entities can have `MultiEdge`s, and they always do.
     if let Some(edge) = world
         .get_component::<MultiEdge>(entity)
         .map(|e| (*e).clone())
     {
-        maintain_fork_and_descendents(cmd, world, entity, &edge.children)
+        maintain_prongs(cmd, world, entity, &edge, traversal)
+    } else if world.get_component::<RetryNode>(entity).is_some() {
+        maintain_retry(cmd, world, entity, traversal)
+    } else if let Some(node) = world.get_component::<TimeoutNode>(entity).map(|n| *n) {
+        maintain_timeout(cmd, world, entity, node, traversal)
+    } else if world.get_component::<BranchNode>(entity).is_some() {
+        maintain_branch(cmd, world, entity, traversal)
+    } else if world.get_component::<RepeatNode>(entity).is_some() {
+        maintain_repeat(cmd, world, entity, traversal)
+    } else if world.get_component::<WhileNode>(entity).is_some() {
+        maintain_while(cmd, world, entity, traversal)
     } else {
-        maintain_task_and_descendents(cmd, world, entity)
+        // Tasks are finished by their runners.
+        maintain_awaited(cmd, world, entity, traversal)
     }
 }
 
-/// Creates a system that traverses all descendents of all finalized entities and unblocks them if
-/// possible.
-///
-/// Also does some garbage collection:
-///   - deletes task graphs with `OnCompletion::Delete`
-///   - removes `FinalTag` components from completed entities
-pub fn build_task_manager_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+/// Maintains `entity` the first time it is reached, then reports it if it has finished. An entity
+/// that was maintained before is not traversed again, since any progress below it comes up from
+/// the ready frontier.
+fn maintain_entity_and_descendents(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if let Some(reached) = traversal.reached {
+        // Once one entity is put off, so are the ones after it, to keep them in order.
+        if traversal.is_putting_off() || needs_traversal(world, entity, traversal) {
+            reached.borrow_mut().push((entity, traversal.from));
+            return None;
+        }
+        return maintain_reached_entity(cmd, world, entity, traversal);
+    }
+
+    // Maintaining an entity first maintains the entities it depends on, which would recurse once
+    // per level of a deep graph. Instead, the entities that need traversing are put off and pushed
+    // on a stack, and the entity that reached them is maintained again once they have been.
+    let reached = RefCell::new(Vec::new());
+    let mut stack = vec![(entity, traversal.from, false)];
+    loop {
+        let (entity, from, is_revisit) = *stack.last().expect("Stack can't be empty");
+        let traversal = Traversal {
+            from,
+            reached: Some(&reached),
+            ..traversal
+        };
+        let outcome = if is_revisit {
+            let outcome = maintain_entity(cmd, world, entity, traversal);
+            if outcome.is_some() {
+                report_finished(world, entity, traversal.events);
+            }
+            outcome
+        } else {
+            maintain_reached_entity(cmd, world, entity, traversal)
+        };
+
+        let reached = reached.take();
+        if reached.is_empty() {
+            stack.pop();
+            if stack.is_empty() {
+                return outcome;
+            }
+        } else {
+            stack.last_mut().expect("Stack can't be empty").2 = true;
+            stack.extend(
+                reached
+                    .into_iter()
+                    .rev()
+                    .map(|(child, from)| (child, from, false)),
+            );
+        }
+    }
+}
+
+/// Whether `entity` exists and hasn't been maintained before.
+fn needs_traversal(world: &SubWorld, entity: Entity, traversal: Traversal<'_>) -> bool {
+    world.get_component::<TaskProgress>(entity).is_some()
+        && !traversal
+            .frontier
+            .borrow()
+            .is_known(entity, traversal.other_graphs)
+}
+
+/// Does the work of `maintain_entity_and_descendents` for a single entity.
+fn maintain_reached_entity(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<TaskOutcome> {
+    if world.get_component::<TaskProgress>(entity).is_none() {
+        // The entity hasn't been created yet, so check again on the next run.
+        if let Some(parent) = traversal.from {
+            traversal.frontier.borrow_mut().mark_ready(parent);
+        }
+        return None;
+    }
+    let is_known =
+        traversal
+            .frontier
+            .borrow_mut()
+            .visit(entity, traversal.from, traversal.other_graphs);
+    let outcome = if is_known {
+        entity_outcome(world, entity)
+    } else {
+        maintain_entity(cmd, world, entity, traversal)
+    };
+    if outcome.is_some() {
+        report_finished(world, entity, traversal.events);
+    }
+
+    outcome
+}
+
+/// Maintains an entity from the ready frontier, then each ancestor that finishes as a result.
+/// Returns the final entity and outcome of the graph if the whole graph finished.
+fn maintain_ready_entity(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    traversal: Traversal<'_>,
+) -> Option<(Entity, TaskOutcome)> {
+    // Ancestors that were cancelled along with `entity` are reported first, like a traversal from
+    // the final entity would.
+    let mut entity = entity;
+    loop {
+        let parent = traversal.frontier.borrow().parent(entity);
+        match parent {
+            Some(parent) if is_finished_unreported(world, parent) => entity = parent,
+            _ => break,
+        }
+    }
+
+    loop {
+        // Maintaining an entity again in the same run could reach entities that it just created.
+        if !traversal.frontier.borrow_mut().maintained.insert(entity) {
+            return None;
+        }
+        let outcome = maintain_entity(cmd, world, entity, traversal)?;
+        if !report_finished(world, entity, traversal.events) {
+            // Whatever depends on `entity` already knows.
+            return None;
+        }
+        let frontier = traversal.frontier.borrow();
+        match frontier.parent(entity) {
+            Some(parent) => entity = parent,
+            None if entity == frontier.root => return Some((entity, outcome)),
+            None => return None,
+        }
+    }
+}
+
+fn is_finished_unreported(world: &SubWorld, entity: Entity) -> bool {
+    world
+        .get_component::<TaskProgress>(entity)
+        .is_some_and(|progress| progress.is_finished() && !progress.is_reported())
+}
+
+/// Whether `entity` should stay in the ready frontier.
+fn is_ready(world: &SubWorld, entity: Entity) -> bool {
+    let is_running = world
+        .get_component::<TaskProgress>(entity)
+        .is_some_and(|progress| progress.is_unblocked() && !progress.is_finished());
+
+    is_running && world.get_component::<MultiEdge>(entity).is_none()
+}
+
+/// Records a `TaskEvent::Finished` for `entity` and its finished descendents, unless they have
+/// already been reported. Descendents that were cancelled along with `entity` are never maintained
+/// again, so this is the only chance to report them. Returns `true` if `entity` itself was
+/// reported.
+fn report_finished(world: &SubWorld, entity: Entity, events: &EventLog) -> bool {
+    if !report_entity(world, entity, events) {
+        return false;
+    }
+    let mut stack = children(world, entity);
+    stack.reverse();
+    while let Some(child) = stack.pop() {
+        // Only newly reported entities can have unreported descendents.
+        if report_entity(world, child, events) {
+            stack.extend(children(world, child).into_iter().rev());
+        }
+    }
+
+    true
+}
+
+/// Records a `TaskEvent::Finished` for `entity` alone. Returns `false` if it hasn't finished or was
+/// already reported.
+fn report_entity(world: &SubWorld, entity: Entity, events: &EventLog) -> bool {
+    let outcome = match world.get_component::<TaskProgress>(entity) {
+        Some(progress) => match progress.outcome() {
+            Some(outcome) if progress.report() => outcome,
+            _ => return false,
+        },
+        None => return false,
+    };
+    events
+        .borrow_mut()
+        .push(TaskEvent::Finished(entity, outcome));
+
+    true
+}
+
+/// Maintains each of `graphs` in parallel on the rayon thread pool. Graphs share no entities, so
+/// each one gets its own `Traversal`, commands and events, and the worker that maintains a graph
+/// owns its `GraphFrontier`. The commands are then applied and the events collected in the order
+/// of `graphs`, which are returned along with their events.
+fn maintain_graphs<F>(
+    cmd: &mut CommandBuffer,
+    world: &mut SubWorld,
+    graphs: Vec<GraphFrontier>,
+    run: &Run<'_>,
+    events: &mut Vec<TaskEvent>,
+    maintain: F,
+) -> Vec<(GraphFrontier, Vec<TaskEvent>)>
+where
+    F: Fn(&mut DeferredCommands, &SubWorld, Traversal<'_>) + Sync,
+{
+    let maintained: Vec<(GraphFrontier, DeferredCommands, Vec<TaskEvent>)> = {
+        let world = &*world;
+        graphs
+            .into_par_iter()
+            .map(|graph| {
+                let mut commands = DeferredCommands::default();
+                let graph_events = EventLog::default();
+                let root = graph.root;
+                let on_failure = graph.final_tag.on_failure;
+                let frontier = RefCell::new(graph);
+                let traversal = Traversal {
+                    on_failure,
+                    root,
+                    from: None,
+                    tick: run.tick,
+                    time: run.time,
+                    events: &graph_events,
+                    frontier: &frontier,
+                    other_graphs: run.other_graphs,
+                    reached: None,
+                };
+                maintain(&mut commands, world, traversal);
+                (frontier.into_inner(), commands, graph_events.into_inner())
+            })
+            .collect()
+    };
+
+    maintained
+        .into_iter()
+        .map(|(graph, commands, graph_events)| {
+            events.extend(graph_events.iter().cloned());
+            commands.apply(cmd, world);
+            (graph, graph_events)
+        })
+        .collect()
+}
+
+/// Traverses a graph that the task manager system hasn't seen before from its final entity.
+fn maintain_new_graph(cmd: &mut DeferredCommands, world: &SubWorld, traversal: Traversal<'_>) {
+    let root = traversal.root;
+    if let Some(outcome) = maintain_entity_and_descendents(cmd, world, root, traversal) {
+        finish_graph(cmd, world, root, outcome, traversal);
+    }
+}
+
+/// Maintains the due entities of a graph in the order they became ready, and keeps the ones that
+/// are still running in the frontier.
+fn maintain_ready_entities(cmd: &mut DeferredCommands, world: &SubWorld, traversal: Traversal<'_>) {
+    let due = std::mem::take(&mut traversal.frontier.borrow_mut().due);
+    for entity in due.into_iter() {
+        // Once the graph has finished, this only finds the entities that a quorum left running.
+        if let Some((root, outcome)) = maintain_ready_entity(cmd, world, entity, traversal) {
+            finish_graph(cmd, world, root, outcome, traversal);
+        }
+        if is_ready(world, entity) {
+            traversal.frontier.borrow_mut().mark_ready(entity);
+        }
+    }
+}
+
+/// Calls the callbacks of a graph that just finished, records its outcome for its `TaskHandle`, and
+/// cleans it up according to its `OnCompletion`.
+fn finish_graph(
+    cmd: &mut DeferredCommands,
+    world: &SubWorld,
+    entity: Entity,
+    outcome: TaskOutcome,
+    traversal: Traversal<'_>,
+) {
+    let final_tag = {
+        let mut frontier = traversal.frontier.borrow_mut();
+        frontier.is_finished = true;
+        frontier.final_tag
+    };
+    let events = traversal.events;
+    events
+        .borrow_mut()
+        .push(TaskEvent::GraphFinished(entity, outcome.clone()));
+    // Callbacks may still want to look at the graph, so run them before it might be deleted.
+    let callback = world
+        .get_component::<CallbacksSlot>(entity)
+        .and_then(|slot| slot.0.lock().unwrap().take(&outcome));
+    if let Some(callback) = callback {
+        log::debug!("Calling callback of {:?}", entity);
+        cmd.push(move |cmd, _| callback(cmd, entity));
+    }
+    // Tell the `TaskHandle` before the graph might be deleted.
+    if let Some(slot) = world.get_component::<HandleSlot>(entity) {
+        slot.finish(FinishedGraph {
+            outcome,
+            output: entity_output(world, entity),
+        });
+    }
+    match final_tag.on_completion {
+        OnCompletion::Delete => {
+            // Prongs left running by a quorum might not have finished.
+            let mut hooks = Vec::new();
+            cancel_entity_and_descendents(world, entity, &mut hooks);
+            submit_cancel_hooks(cmd, hooks);
+            delete_entity_and_descendents(cmd, world, entity, events);
+        }
+        OnCompletion::None => {
+            log::debug!("Removing FinalTag from {:?}", entity);
+            // PERF: avoid this?
+            cmd.remove_component::<FinalTag>(entity);
+        }
+    }
+}
+
+/// The type of `Query` the task manager system uses to find the final entities of graphs.
+type FinalTagQuery = Query<Read<FinalTag>, <Read<FinalTag> as DefaultFilter>::Filter>;
+
+/// What the task manager system remembers between runs.
+#[derive(Default)]
+struct TaskManager {
+    frontier: Frontier,
+    tick: u64,
+}
+
+impl TaskManager {
+    /// Maintains all task graphs once, returning the events of this run.
+    fn run(
+        &mut self,
+        cmd: &mut CommandBuffer,
+        world: &mut SubWorld,
+        final_tasks_query: &mut FinalTagQuery,
+    ) -> Vec<TaskEvent> {
+        self.tick += 1;
+        let time = Instant::now();
+        let mut events = Vec::new();
+        // Entities that become ready during this run may depend on entities that were just
+        // created by commands, so they are only maintained on the next run.
+        self.frontier.start_run();
+
+        // New graphs, including graphs that were appended to others, are traversed in full.
+        let new_graphs: Vec<(Entity, FinalTag)> = final_tasks_query
+            .iter_entities(world)
+            .filter(|(e, _)| !self.frontier.graphs.contains_key(e))
+            .map(|(e, f)| (e, *f))
+            .collect();
+        let new_graphs: Vec<GraphFrontier> = new_graphs
+            .into_iter()
+            .map(|(root, final_tag)| self.frontier.new_graph(root, final_tag))
+            .collect();
+        let run = Run {
+            tick: self.tick,
+            time,
+            other_graphs: &self.frontier.graphs,
+        };
+        let maintained = maintain_graphs(
+            cmd,
+            world,
+            new_graphs,
+            &run,
+            &mut events,
+            maintain_new_graph,
+        );
+        self.frontier.keep(maintained);
+
+        // Graphs that were traversed before can only make progress from their due entities.
+        let due_graphs = self.frontier.take_due_graphs();
+        let run = Run {
+            tick: self.tick,
+            time,
+            other_graphs: &self.frontier.graphs,
+        };
+        let maintained = maintain_graphs(
+            cmd,
+            world,
+            due_graphs,
+            &run,
+            &mut events,
+            maintain_ready_entities,
+        );
+        self.frontier.keep(maintained);
+
+        events
+    }
+}
+
+/// Declares the components the task manager system reads and writes.
+fn task_manager_system_builder<I: Into<SystemId>>(id: I) -> SystemBuilder {
     SystemBuilder::new(id)
         .read_component::<MultiEdge>()
         .write_component::<MultiEdge>()
@@ -165,27 +1472,109 @@ pub fn build_task_manager_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulabl
         .write_component::<SingleEdge>()
         .read_component::<TaskProgress>()
         .write_component::<TaskProgress>()
+        .read_component::<RetryNode>()
+        .write_component::<RetryNode>()
+        .read_component::<TimeoutNode>()
+        .read_component::<BranchNode>()
+        .write_component::<BranchNode>()
+        .read_component::<RepeatNode>()
+        .write_component::<RepeatNode>()
+        .read_component::<WhileNode>()
+        .write_component::<WhileNode>()
+        .read_component::<InnerEdge>()
+        .write_component::<InnerEdge>()
+        .read_component::<HandleSlot>()
+        .read_component::<CallbacksSlot>()
+}
+
+/// Creates a system that unblocks the descendents of finalized entities once they can run. Each
+/// graph is traversed from its final entity the first time the system sees it. After that, the
+/// system only maintains the tasks and control entities that are unblocked, and the entities that
+/// depend on the ones that finished, so its cost doesn't grow with graphs that are waiting.
+/// Failures are propagated according to the `OnFailure` of each graph, and nodes that exceed their
+/// `Timeout` are stopped. Separate graphs are maintained in parallel on the rayon thread pool, and
+/// their commands and events are put in order afterwards, so the result doesn't depend on the
+/// number of threads.
+///
+/// Also calls the `GraphCallbacks` of finished graphs, records how each graph finished for its
+/// `TaskHandle`, and does some garbage collection:
+///   - deletes task graphs with `OnCompletion::Delete`
+///   - removes `FinalTag` components from finished entities
+pub fn build_task_manager_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+    let mut manager = TaskManager::default();
+
+    task_manager_system_builder(id)
         .with_query(<Read<FinalTag>>::query())
-        .build(|cmd, world, _, final_tasks_query| {
-            let final_entities: Vec<(Entity, FinalTag)> = final_tasks_query
-                .iter_entities(world)
-                .map(|(e, f)| (e, *f))
-                .collect();
-
-            for (entity, FinalTag { on_completion }) in final_entities.into_iter() {
-                let final_complete = maintain_entity_and_descendents(cmd, world, entity);
-                if final_complete {
-                    match on_completion {
-                        OnCompletion::Delete => {
-                            delete_entity_and_descendents(cmd, world, entity);
-                        }
-                        OnCompletion::None => {
-                            log::debug!("Removing FinalTag from {:?}", entity);
-                            // PERF: avoid this?
-                            cmd.remove_component::<FinalTag>(entity);
-                        }
-                    }
-                }
-            }
+        .build(move |cmd, world, _, final_tasks_query| {
+            manager.run(cmd, world, final_tasks_query);
+        })
+}
+
+/// Like `build_task_manager_system`, but also adds the `TaskEvent`s of each run to the
+/// `TaskEvents` resource, which must exist.
+pub fn build_task_manager_system_with_events<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+    let mut manager = TaskManager::default();
+
+    task_manager_system_builder(id)
+        .write_resource::<TaskEvents>()
+        .with_query(<Read<FinalTag>>::query())
+        .build(move |cmd, world, task_events, final_tasks_query| {
+            let events = manager.run(cmd, world, final_tasks_query);
+            task_events.events.extend(events);
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn separate_graphs_are_maintained_at_the_same_time() {
+        let mut world = World::new();
+        let roots = world.insert((), vec![(0u8,), (0u8,)]).to_vec();
+        let mut cmd = CommandBuffer::new(&world);
+        let mut frontier = Frontier::default();
+        let graphs = roots
+            .iter()
+            .map(|&root| frontier.new_graph(root, FinalTag::default()))
+            .collect();
+        let run = Run {
+            tick: 1,
+            time: Instant::now(),
+            other_graphs: &frontier.graphs,
+        };
+
+        // Each worker waits for the other one to start, which only happens in time if nothing
+        // makes them take turns.
+        let started = AtomicUsize::new(0);
+        let met = AtomicUsize::new(0);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let mut world = SubWorld::from(&mut world);
+            maintain_graphs(
+                &mut cmd,
+                &mut world,
+                graphs,
+                &run,
+                &mut Vec::new(),
+                |_, _, _| {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    let deadline = Instant::now() + Duration::from_secs(10);
+                    while started.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+                        std::thread::yield_now();
+                    }
+                    if started.load(Ordering::SeqCst) == 2 {
+                        met.fetch_add(1, Ordering::SeqCst);
+                    }
+                },
+            );
+        });
+
+        assert_eq!(met.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,10 +1,17 @@
-use crate::components::{TaskComponent, TaskProgress};
+use crate::clock::TaskClock;
+use crate::components::{
+    ParTaskComponent, RunnableTag, TaskComponent, TaskError, TaskOutcome, TaskProgress, TaskStatus,
+};
+use crate::context::{ParTaskContext, TaskContext};
+use crate::manager::cancel;
 
 use legion::{
     filter::{And, ComponentFilter, EntityFilterTuple, Passthrough},
     prelude::*,
     query::Query,
 };
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 /// The type of `SystemQuery` created by `task_runner_query` and used by `run_tasks`.
 pub type TaskSystemQuery<T> = Query<(Read<TaskProgress>, Write<T>), TaskEntityFilter<T>>;
@@ -14,30 +21,176 @@ pub type TaskQuery<T> = Query<(Read<TaskProgress>, Write<T>), TaskEntityFilter<T
 
 /// The `EntityFilterTuple` for `task_runner_query`.
 pub type TaskEntityFilter<T> = EntityFilterTuple<
-    And<(ComponentFilter<TaskProgress>, ComponentFilter<T>)>,
+    And<(
+        ComponentFilter<TaskProgress>,
+        ComponentFilter<T>,
+        ComponentFilter<RunnableTag>,
+    )>,
     And<(Passthrough, Passthrough)>,
     And<(Passthrough, Passthrough)>,
 >;
 
 /// Run the tasks that match `task_query`. Should be run in a `System` created with
-/// `task_runner_query`.
+/// `task_runner_query`, passing on the system's command buffer, which removes the `RunnableTag` of
+/// tasks that finish, and the `TaskClock` resource, which the tasks see through their
+/// `TaskContext`.
 pub fn run_tasks<'a, T: 'static + TaskComponent<'a>>(
+    cmd: &mut CommandBuffer,
     world: &mut SubWorld,
+    clock: &TaskClock,
     task_component_data: &mut T::Data,
     task_query: &mut TaskSystemQuery<T>,
 ) {
-    for (task_progress, mut task) in task_query.iter_mut(world) {
-        if !task_progress.is_unblocked || task_progress.is_complete() {
+    for (entity, (task_progress, mut task)) in task_query.iter_entities_mut(world) {
+        if !is_runnable(&task_progress) {
+            continue;
+        }
+        if let Err(error) = give_input(&mut *task, &task_progress) {
+            reject_input(cmd, entity, &task_progress, error);
             continue;
         }
-        let is_complete = task.run(task_component_data);
-        if is_complete {
-            task_progress.complete();
+        let mut ctx = TaskContext::new(entity, &task_progress, clock, cmd);
+        if task_progress.start(clock.tick()) {
+            task.on_unblocked(task_component_data, &mut ctx);
         }
+        let status = task.run(task_component_data, &mut ctx);
+        let outcome = match check_awaiting(&task_progress, status) {
+            TaskStatus::Running => continue,
+            TaskStatus::Complete => {
+                task_progress.set_output(task.take_output());
+                TaskOutcome::Complete
+            }
+            TaskStatus::Failed(error) => {
+                log::debug!("Task {:?} failed: {}", entity, error);
+                TaskOutcome::Failed(error)
+            }
+        };
+        task.on_finished(task_component_data, &mut ctx, &outcome);
+        task_progress.finish(outcome);
+        cancel_awaited(cmd, &task_progress);
+        cmd.remove_component::<RunnableTag>(entity);
     }
 }
 
-/// The legion system query required to run all tasks with `T: TaskComponent`.
+/// Like `run_tasks`, but runs the tasks in parallel through `ParTaskComponent::par_run`, which only
+/// gets shared access to `task_component_data`. The hooks of `TaskComponent` are still called one
+/// task at a time, before and after the tasks run.
+pub fn par_run_tasks<'a, T>(
+    cmd: &mut CommandBuffer,
+    world: &mut SubWorld,
+    clock: &TaskClock,
+    task_component_data: &mut T::Data,
+    task_query: &mut TaskSystemQuery<T>,
+) where
+    T: 'static + ParTaskComponent<'a>,
+    T::Data: Sync,
+{
+    for (entity, (task_progress, mut task)) in task_query.iter_entities_mut(world) {
+        if !is_runnable(&task_progress) {
+            continue;
+        }
+        if let Err(error) = give_input(&mut *task, &task_progress) {
+            reject_input(cmd, entity, &task_progress, error);
+            continue;
+        }
+        if task_progress.start(clock.tick()) {
+            let mut ctx = TaskContext::new(entity, &task_progress, clock, cmd);
+            task.on_unblocked(task_component_data, &mut ctx);
+        }
+    }
+
+    // Tasks that finish are only marked as finished after their `on_finished` hook, like in
+    // `run_tasks`.
+    let finished = Mutex::new(HashMap::new());
+    let data = &*task_component_data;
+    task_query.par_entities_for_each_mut(world, |(entity, (task_progress, mut task))| {
+        if !is_runnable(&task_progress) {
+            return;
+        }
+        let mut ctx = ParTaskContext::new(entity, &task_progress, clock);
+        let outcome = match check_awaiting(&task_progress, task.par_run(data, &mut ctx)) {
+            TaskStatus::Running => return,
+            TaskStatus::Complete => {
+                task_progress.set_output(task.take_output());
+                TaskOutcome::Complete
+            }
+            TaskStatus::Failed(error) => {
+                log::debug!("Task {:?} failed: {}", entity, error);
+                TaskOutcome::Failed(error)
+            }
+        };
+        finished.lock().unwrap().insert(entity, outcome);
+    });
+
+    let mut finished = finished.into_inner().unwrap();
+    if finished.is_empty() {
+        return;
+    }
+    for (entity, (task_progress, mut task)) in task_query.iter_entities_mut(world) {
+        let outcome = match finished.remove(&entity) {
+            Some(outcome) => outcome,
+            None => continue,
+        };
+        let mut ctx = TaskContext::new(entity, &task_progress, clock, cmd);
+        task.on_finished(task_component_data, &mut ctx, &outcome);
+        task_progress.finish(outcome);
+        cancel_awaited(cmd, &task_progress);
+        cmd.remove_component::<RunnableTag>(entity);
+    }
+}
+
+/// A task that spawned a graph with `TaskContext::spawn_and_await` has to keep running until the
+/// graph finishes, since only then does it get the graph's output. If it completes anyway, it fails
+/// instead.
+fn check_awaiting(task_progress: &TaskProgress, status: TaskStatus) -> TaskStatus {
+    match status {
+        TaskStatus::Complete if task_progress.awaited().is_some() => TaskStatus::Failed(
+            TaskError::new("completed while awaiting a graph it spawned"),
+        ),
+        status => status,
+    }
+}
+
+/// Cancels the graph that a task spawned to await, if the task finished without waiting for it.
+/// Nothing would run the graph otherwise.
+fn cancel_awaited(cmd: &CommandBuffer, task_progress: &TaskProgress) {
+    if let Some(awaited) = task_progress.awaited() {
+        cancel(cmd, awaited);
+    }
+}
+
+/// Passes the task the output of the entity it depends on, the first time it is run.
+fn give_input<'a, T: TaskComponent<'a>>(
+    task: &mut T,
+    task_progress: &TaskProgress,
+) -> Result<(), TaskError> {
+    match task_progress.take_input() {
+        Some(input) => task.set_input(input),
+        None => Ok(()),
+    }
+}
+
+/// Fails a task that rejected its input. The task never started, so it gets neither
+/// `TaskComponent::on_unblocked` nor `TaskComponent::on_finished`.
+fn reject_input(
+    cmd: &mut CommandBuffer,
+    entity: Entity,
+    task_progress: &TaskProgress,
+    error: TaskError,
+) {
+    log::debug!("Task {:?} rejected its input: {}", entity, error);
+    task_progress.finish(TaskOutcome::Failed(error));
+    cmd.remove_component::<RunnableTag>(entity);
+}
+
+fn is_runnable(task_progress: &TaskProgress) -> bool {
+    task_progress.is_unblocked()
+        && !task_progress.is_finished()
+        && task_progress.awaited().is_none()
+}
+
+/// The legion system query required to run all tasks with `T: TaskComponent`. Only matches the
+/// tasks that the task manager has unblocked and that haven't finished yet.
 pub fn task_runner_query<'a, T: 'static + TaskComponent<'a>>() -> TaskQuery<T> {
-    <(Read<TaskProgress>, Write<T>)>::query()
+    <(Read<TaskProgress>, Write<T>)>::query().filter(component::<RunnableTag>())
 }
@@ -0,0 +1,84 @@
+use crate::clock::TaskClock;
+use crate::components::{TaskComponent, TaskStatus};
+use crate::context::TaskContext;
+use crate::runner::{run_tasks, task_runner_query};
+
+use legion::{prelude::*, systems::SystemId};
+use std::time::Duration;
+
+/// A task that completes once the `TaskClock` has ticked `ticks` times after the task first ran.
+#[derive(Clone, Debug)]
+pub struct WaitTicks {
+    ticks: u64,
+    until: Option<u64>,
+}
+
+impl WaitTicks {
+    pub fn new(ticks: u64) -> Self {
+        WaitTicks { ticks, until: None }
+    }
+}
+
+impl<'a> TaskComponent<'a> for WaitTicks {
+    type Data = TaskClock;
+
+    fn run(&mut self, clock: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+        let until = *self.until.get_or_insert(clock.tick() + self.ticks);
+        if clock.tick() >= until {
+            TaskStatus::Complete
+        } else {
+            TaskStatus::Running
+        }
+    }
+}
+
+/// A task that completes once `duration` of `TaskClock` time has elapsed after the task first ran.
+#[derive(Clone, Debug)]
+pub struct WaitDuration {
+    duration: Duration,
+    until: Option<Duration>,
+}
+
+impl WaitDuration {
+    pub fn new(duration: Duration) -> Self {
+        WaitDuration {
+            duration,
+            until: None,
+        }
+    }
+}
+
+impl<'a> TaskComponent<'a> for WaitDuration {
+    type Data = TaskClock;
+
+    fn run(&mut self, clock: &mut Self::Data, _ctx: &mut TaskContext) -> TaskStatus {
+        let until = *self.until.get_or_insert(clock.elapsed() + self.duration);
+        if clock.elapsed() >= until {
+            TaskStatus::Complete
+        } else {
+            TaskStatus::Running
+        }
+    }
+}
+
+/// Creates a system that runs `WaitTicks` tasks. Requires a `TaskClock` resource.
+pub fn build_wait_ticks_runner_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+    SystemBuilder::new(id)
+        .read_resource::<TaskClock>()
+        .with_query(task_runner_query::<WaitTicks>())
+        .build(|cmd, world, clock, task_query| {
+            let mut data = **clock;
+            run_tasks(cmd, world, clock, &mut data, task_query)
+        })
+}
+
+/// Creates a system that runs `WaitDuration` tasks. Requires a `TaskClock` resource.
+pub fn build_wait_duration_runner_system<I: Into<SystemId>>(id: I) -> Box<dyn Schedulable> {
+    SystemBuilder::new(id)
+        .read_resource::<TaskClock>()
+        .with_query(task_runner_query::<WaitDuration>())
+        .build(|cmd, world, clock, task_query| {
+            let mut data = **clock;
+            run_tasks(cmd, world, clock, &mut data, task_query)
+        })
+}